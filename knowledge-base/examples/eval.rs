@@ -0,0 +1,174 @@
+//! Command line client for the `/admin/evals` API.
+//!
+//! ```sh
+//! KB_USER=admin KB_PASSWORD=... cargo run --example eval -- import faq-golden golden.jsonl
+//! cargo run --example eval -- run 1 --label bge-small --mode hybrid --vectors questions,keywords --wait
+//! cargo run --example eval -- runs 1
+//! cargo run --example eval -- show 7
+//! ```
+//!
+//! Dataset files are a JSON array or JSON lines of
+//! `{"question": "...", "expected_answer": "...", "expected_doc_ids": ["12"]}`.
+
+use std::env;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::Client;
+use serde_json::{json, Map, Value};
+
+const USAGE: &str = "usage: eval <datasets | import NAME FILE | run DATASET_ID [--label L] [--model M] \
+[--mode dense|sparse|hybrid] [--vectors a,b] [--top-k N] [--rerank true|false] [--k N] [--no-judge] [--wait] \
+| runs [DATASET_ID] | show RUN_ID>";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let base_url = env::var("KB_URL").unwrap_or_else(|_| "http://localhost:8000".to_string());
+    let client = login(&base_url).await?;
+
+    match args.first().map(String::as_str) {
+        Some("datasets") => {
+            let datasets = get(&client, &format!("{base_url}/admin/evals/datasets")).await?;
+            for d in datasets.as_array().into_iter().flatten() {
+                println!("{:>6}  {}  {}", d["id"], d["name"].as_str().unwrap_or_default(),
+                         d["description"].as_str().unwrap_or_default());
+            }
+        }
+        Some("import") => {
+            let (name, file) = match (args.get(1), args.get(2)) {
+                (Some(name), Some(file)) => (name, file),
+                _ => bail!(USAGE),
+            };
+            let cases = read_cases(file)?;
+            let dataset = post(&client, &format!("{base_url}/admin/evals/datasets"),
+                               json!({ "name": name, "cases": cases })).await?;
+            println!("dataset {} created with {} cases", dataset["id"],
+                     dataset["cases"].as_array().map(Vec::len).unwrap_or_default());
+        }
+        Some("run") => {
+            let dataset_id = args.get(1).ok_or_else(|| anyhow!(USAGE))?;
+            let (body, wait) = run_body(&args[2..])?;
+            let run = post(&client, &format!("{base_url}/admin/evals/datasets/{dataset_id}/runs"), body).await?;
+            println!("run {} started", run["id"]);
+            if wait {
+                let run = wait_for_run(&client, &base_url, &run["id"]).await?;
+                print_metrics(&run);
+            }
+        }
+        Some("runs") => {
+            let url = match args.get(1) {
+                Some(dataset_id) => format!("{base_url}/admin/evals/runs?dataset_id={dataset_id}"),
+                None => format!("{base_url}/admin/evals/runs"),
+            };
+            println!("{:>6}  {:<8} {:<20} {:>7} {:>7} {:>7} {:>7} {:>7} {:>9}",
+                     "run", "status", "label", "recall", "mrr", "ndcg", "faith", "relev", "latency");
+            for run in get(&client, &url).await?.as_array().into_iter().flatten() {
+                print_metrics(run);
+            }
+        }
+        Some("show") => {
+            let run_id = args.get(1).ok_or_else(|| anyhow!(USAGE))?;
+            let run = get(&client, &format!("{base_url}/admin/evals/runs/{run_id}")).await?;
+            println!("{}", serde_json::to_string_pretty(&run)?);
+        }
+        _ => bail!(USAGE),
+    }
+
+    Ok(())
+}
+
+async fn login(base_url: &str) -> Result<Client> {
+    let client = Client::builder().cookie_store(true).build()?;
+    let username = env::var("KB_USER").context("KB_USER not set")?;
+    let password = env::var("KB_PASSWORD").context("KB_PASSWORD not set")?;
+
+    let res = client
+        .post(format!("{base_url}/api/login"))
+        .json(&json!({ "username": username, "password": password }))
+        .send()
+        .await?;
+    let body: Value = res.json().await?;
+    if !body["result"]["success"].as_bool().unwrap_or(false) {
+        bail!("login failed: {body}");
+    }
+    Ok(client)
+}
+
+async fn get(client: &Client, url: &str) -> Result<Value> {
+    let res = client.get(url).send().await?;
+    check(res).await
+}
+
+async fn post(client: &Client, url: &str, body: Value) -> Result<Value> {
+    let res = client.post(url).json(&body).send().await?;
+    check(res).await
+}
+
+async fn check(res: reqwest::Response) -> Result<Value> {
+    let status = res.status();
+    let body: Value = res.json().await.unwrap_or(Value::Null);
+    if !status.is_success() {
+        bail!("{status}: {body}");
+    }
+    Ok(body)
+}
+
+fn read_cases(file: &str) -> Result<Vec<Value>> {
+    let content = std::fs::read_to_string(file).with_context(|| format!("reading {file}"))?;
+    if content.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(&content)?);
+    }
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(Into::into))
+        .collect()
+}
+
+/// Turns `--flag value` pairs into the run request body.
+fn run_body(args: &[String]) -> Result<(Value, bool)> {
+    let mut body = Map::new();
+    let mut wait = false;
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(|| anyhow!("missing value for {flag}"));
+        match flag.as_str() {
+            "--label" => { body.insert("label".into(), value()?.into()); }
+            "--model" => { body.insert("model".into(), value()?.into()); }
+            "--mode" => { body.insert("mode".into(), value()?.into()); }
+            "--vectors" => {
+                let vectors: Vec<Value> = value()?.split(',').map(|v| v.trim().into()).collect();
+                body.insert("vectors".into(), vectors.into());
+            }
+            "--top-k" => { body.insert("top_k".into(), value()?.parse::<u64>()?.into()); }
+            "--rerank" => { body.insert("rerank".into(), value()?.parse::<bool>()?.into()); }
+            "--k" => { body.insert("k".into(), value()?.parse::<u64>()?.into()); }
+            "--no-judge" => { body.insert("judge".into(), false.into()); }
+            "--wait" => wait = true,
+            other => bail!("unknown flag {other}\n{USAGE}"),
+        }
+    }
+    Ok((Value::Object(body), wait))
+}
+
+async fn wait_for_run(client: &Client, base_url: &str, run_id: &Value) -> Result<Value> {
+    loop {
+        let run = get(client, &format!("{base_url}/admin/evals/runs/{run_id}")).await?;
+        if run["status"] != "running" {
+            return Ok(run);
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
+
+fn print_metrics(run: &Value) {
+    let m = &run["metrics"];
+    let num = |v: &Value| v.as_f64().map(|v| format!("{v:.3}")).unwrap_or_else(|| "-".to_string());
+    println!("{:>6}  {:<8} {:<20} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}ms",
+             run["id"], run["status"].as_str().unwrap_or_default(),
+             run["label"].as_str().unwrap_or_default(),
+             num(&m["recall_at_k"]), num(&m["mrr"]), num(&m["ndcg_at_k"]),
+             num(&m["faithfulness"]), num(&m["answer_relevance"]),
+             m["avg_latency_ms"].as_f64().map(|v| v.round()).unwrap_or_default());
+}
@@ -19,13 +19,20 @@ CREATE TABLE document (
     id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
     filename VARCHAR(256) NOT NULL,
     filepath VARCHAR(512) NOT NULL,
-    uploaded_by BIGINT NOT NULL REFERENCES "user"(id)
+    uploaded_by BIGINT NOT NULL REFERENCES "user"(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
 create table conversation (
                               id           bigserial primary key,
                               owner_id     bigint  not null references "user"(id) on delete cascade,
                               title        text    not null default 'Untitled chat',
+                              model        text,
+                              active_leaf_id bigint,
+                              archived     boolean not null default false,
+                              pinned       boolean not null default false,
+                              summary      text,
+                              summary_until_id bigint,
                               created_at   timestamptz default now(),
                               updated_at   timestamptz default now()
 );
@@ -33,19 +40,143 @@ create table conversation (
 create table message (
                          id              bigserial primary key,
                          conversation_id bigint  not null references conversation(id) on delete cascade,
+                         parent_id       bigint  references message(id) on delete cascade,
                          sender          text    not null check (sender in ('user','assistant')),
                          content         text    not null,
                          token_count     int     not null,
+                         confidence      double precision,
+                         grounded        boolean,
+                         status          text    not null default 'complete' check (status in ('complete','cancelled')),
                          created_at      timestamptz default now()
 );
 
 create index on message(conversation_id, id);
+create index on message(parent_id);
+create index on message using gin (to_tsvector('english', content));
+create index on conversation using gin (to_tsvector('english', title));
+create index on conversation(owner_id, archived, pinned, updated_at);
+
+alter table conversation
+    add foreign key (active_leaf_id) references message(id) on delete set null,
+    add foreign key (summary_until_id) references message(id) on delete set null;
+
+create table conversation_collaborator (
+                                          conversation_id bigint not null references conversation(id) on delete cascade,
+                                          user_id         bigint not null references "user"(id) on delete cascade,
+                                          role            text   not null check (role in ('viewer','editor')),
+                                          added_at        timestamptz not null default now(),
+                                          primary key (conversation_id, user_id)
+);
+create index on conversation_collaborator(user_id);
+
+create table conversation_share (
+                                    id              bigserial primary key,
+                                    conversation_id bigint not null references conversation(id) on delete cascade,
+                                    token           text   not null unique,
+                                    created_by      bigint not null references "user"(id) on delete cascade,
+                                    snapshot        jsonb  not null,
+                                    expires_at      timestamptz,
+                                    revoked_at      timestamptz,
+                                    created_at      timestamptz not null default now()
+);
+create index on conversation_share(conversation_id);
 
 CREATE TABLE pipeline_log (
                               id               BIGSERIAL PRIMARY KEY,
                               user_id          BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
                               pipeline         TEXT    NOT NULL,
                               duration_ms      INTEGER NOT NULL,
+                              status           TEXT    NOT NULL DEFAULT 'ok' CHECK (status IN ('ok','error')),
+                              preset           TEXT,
+                              config           JSONB,
                               created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
-CREATE INDEX ON pipeline_log(created_at);
\ No newline at end of file
+CREATE INDEX ON pipeline_log(created_at);
+CREATE INDEX ON pipeline_log(pipeline, created_at);
+
+CREATE TABLE eval_dataset (
+                              id               BIGSERIAL PRIMARY KEY,
+                              name             TEXT    NOT NULL UNIQUE,
+                              description      TEXT,
+                              created_by       BIGINT  NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+                              created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE eval_case (
+                           id               BIGSERIAL PRIMARY KEY,
+                           dataset_id       BIGINT  NOT NULL REFERENCES eval_dataset(id) ON DELETE CASCADE,
+                           question         TEXT    NOT NULL,
+                           expected_answer  TEXT,
+                           expected_doc_ids TEXT[]  NOT NULL DEFAULT '{}'
+);
+CREATE INDEX ON eval_case(dataset_id, id);
+
+CREATE TABLE eval_run (
+                          id               BIGSERIAL PRIMARY KEY,
+                          dataset_id       BIGINT  NOT NULL REFERENCES eval_dataset(id) ON DELETE CASCADE,
+                          label            TEXT,
+                          config           JSONB   NOT NULL,
+                          status           TEXT    NOT NULL DEFAULT 'running' CHECK (status IN ('running','done','error')),
+                          metrics          JSONB,
+                          created_by       BIGINT  NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+                          created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                          finished_at      TIMESTAMPTZ
+);
+CREATE INDEX ON eval_run(dataset_id, created_at);
+
+CREATE TABLE eval_result (
+                             id                BIGSERIAL PRIMARY KEY,
+                             run_id            BIGINT  NOT NULL REFERENCES eval_run(id) ON DELETE CASCADE,
+                             case_id           BIGINT  NOT NULL REFERENCES eval_case(id) ON DELETE CASCADE,
+                             retrieved_doc_ids TEXT[]  NOT NULL DEFAULT '{}',
+                             answer            TEXT,
+                             recall            DOUBLE PRECISION,
+                             mrr               DOUBLE PRECISION,
+                             ndcg              DOUBLE PRECISION,
+                             faithfulness      DOUBLE PRECISION,
+                             answer_relevance  DOUBLE PRECISION,
+                             latency_ms        INTEGER NOT NULL,
+                             error             TEXT
+);
+CREATE INDEX ON eval_result(run_id);
+
+CREATE TABLE prompt_template (
+                                 id               BIGSERIAL PRIMARY KEY,
+                                 name             TEXT    NOT NULL,
+                                 version          INTEGER NOT NULL,
+                                 template         TEXT    NOT NULL,
+                                 variables        TEXT[]  NOT NULL DEFAULT '{}',
+                                 description      TEXT,
+                                 created_by       BIGINT  NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+                                 created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                                 UNIQUE (name, version)
+);
+
+-- pipeline config and retrieved chunks an assistant message was answered with
+CREATE TABLE message_trace (
+                               message_id       BIGINT  PRIMARY KEY REFERENCES message(id) ON DELETE CASCADE,
+                               pipeline         JSONB,
+                               documents        JSONB   NOT NULL DEFAULT '[]',
+                               contexts         TEXT[]  NOT NULL DEFAULT '{}',
+                               -- tool calls of an agent-mode answer
+                               agent_steps      JSONB   NOT NULL DEFAULT '[]'
+);
+
+CREATE TABLE message_feedback (
+                                  id               BIGSERIAL PRIMARY KEY,
+                                  message_id       BIGINT  NOT NULL REFERENCES message(id) ON DELETE CASCADE,
+                                  user_id          BIGINT  NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+                                  rating           TEXT    NOT NULL CHECK (rating IN ('up','down')),
+                                  comment          TEXT,
+                                  flagged_sources  TEXT[]  NOT NULL DEFAULT '{}',
+                                  created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                                  updated_at       TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                                  UNIQUE (message_id, user_id)
+);
+CREATE INDEX ON message_feedback(rating, created_at);
+
+-- where a task accepted from extracted action items came from
+ALTER TABLE task
+    ADD COLUMN source_document_id BIGINT REFERENCES document(id) ON DELETE SET NULL,
+    ADD COLUMN source_message_id  BIGINT REFERENCES message(id) ON DELETE SET NULL,
+    ADD COLUMN source_quote       TEXT;
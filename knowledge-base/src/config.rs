@@ -1,4 +1,7 @@
+use crate::model::pipeline::{self, PipelineConfig};
+use crate::model::retrieval::{NamedVector, RetrievalMode};
 use crate::{Error, Result};
+use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
 use std::sync::OnceLock;
@@ -29,10 +32,83 @@ pub struct Config {
     pub JAEGER_ENDPOINT: String,
     pub OLLAMA_URL: String,
     pub DATABASE_URL: String,
+
+    // -- LLM providers
+    pub LLM_PROVIDER: String,
+    pub LLM_BASE_URL: Option<String>,
+    pub LLM_API_KEY: Option<String>,
+    pub PROMPT_MODEL: String,
+    pub LLM_ALLOWED_MODELS: Vec<String>,
+
+    pub EMBED_PROVIDER: String,
+    pub EMBED_BASE_URL: Option<String>,
+    pub EMBED_API_KEY: Option<String>,
+    pub EMBED_MODEL: String,
+    /// Skips the startup probe used to size the Qdrant vectors.
+    pub EMBED_DIMENSIONS: Option<usize>,
+    /// fastembed sparse model (e.g. `Qdrant/Splade_PP_en_v1`).
+    pub SPARSE_EMBED_MODEL: Option<String>,
+    pub FASTEMBED_CACHE_DIR: Option<String>,
+
+    // -- Retrieval
+    /// `dense`, `sparse` or `hybrid`; defaults to hybrid when a sparse model is configured.
+    pub RETRIEVAL_MODE: RetrievalMode,
+    pub RETRIEVAL_TOP_K: u64,
+    /// Named vectors searched by default, e.g. `combined` or `questions,keywords`.
+    pub RETRIEVAL_VECTORS: Vec<String>,
+    pub HYBRID_DENSE_WEIGHT: f32,
+    pub HYBRID_SPARSE_WEIGHT: f32,
+    /// fastembed cross-encoder (e.g. `BAAI/bge-reranker-base`); reranking is off when unset.
+    pub RERANK_MODEL: Option<String>,
+    /// Documents retrieved as reranking candidates.
+    pub RERANK_CANDIDATES: u64,
+    /// Documents kept after reranking.
+    pub RERANK_TOP_N: usize,
+    /// Documents scoring below this are dropped before answering; `rerank_score` when reranked.
+    pub MIN_RETRIEVAL_SCORE: Option<f32>,
+
+    // -- Answers
+    /// Verify every answer against its contexts with the prompt model.
+    pub GROUNDEDNESS_CHECK: bool,
+    /// Groundedness score below which an answer is reported as not grounded.
+    pub GROUNDEDNESS_THRESHOLD: f64,
+    /// Reply when no document passes retrieval.
+    pub NO_ANSWER_MESSAGE: String,
+
+    // -- Query pipelines
+    /// Named pipeline configs, from a JSON object in `SERVICE_PIPELINE_PRESETS`.
+    pub PIPELINE_PRESETS: HashMap<String, PipelineConfig>,
+    pub DEFAULT_PIPELINE: String,
+    /// Rephrasings generated by the `multi_query` stage.
+    pub MULTI_QUERY_VARIANTS: usize,
+
+    // -- Answer cache
+    /// Lifetime of cached answers; `0` disables the cache.
+    pub ANSWER_CACHE_TTL_SECS: u64,
+    /// Cosine similarity above which a near-duplicate question reuses a cached answer.
+    pub ANSWER_CACHE_SIMILARITY: Option<f32>,
+    /// Keep cached answers per user, for when users see different documents.
+    pub ANSWER_CACHE_PER_USER: bool,
+
+    // -- Chat
+    /// Tokens of recent messages sent along with a chat prompt; older
+    /// messages are folded into the conversation summary.
+    pub CHAT_HISTORY_TOKENS: i32,
+    /// Generate a title from the first exchange of untitled conversations.
+    pub CHAT_AUTO_TITLE: bool,
+    /// Lifetime of public share links created without an explicit expiry.
+    pub CHAT_SHARE_TTL_HOURS: i64,
+    /// Tool calls an agent-mode answer may make before it has to answer.
+    pub AGENT_MAX_STEPS: usize,
 }
 
 impl Config {
     fn load_from_env() -> Result<Config> {
+        let embed_provider = get_env_opt("SERVICE_EMBED_PROVIDER")
+            .unwrap_or_else(|| get_env_or("SERVICE_LLM_PROVIDER", "ollama"));
+        let sparse_embed_model = get_env_opt("SERVICE_SPARSE_EMBED_MODEL");
+        let default_retrieval_mode = if sparse_embed_model.is_some() { RetrievalMode::Hybrid } else { RetrievalMode::Dense };
+
         Ok(Config {
             PWD_KEY: get_env_b64u_as_u8s("SERVICE_PWD_KEY")?,
             TOKEN_KEY: get_env_b64u_as_u8s("SERVICE_TOKEN_KEY")?,
@@ -52,20 +128,111 @@ impl Config {
 
             DATABASE_URL: get_env("SERVICE_DATABASE_URL")?,
 
-
+            LLM_PROVIDER: get_env_or("SERVICE_LLM_PROVIDER", "ollama"),
+            LLM_BASE_URL: get_env_opt("SERVICE_LLM_BASE_URL"),
+            LLM_API_KEY: get_env_opt("SERVICE_LLM_API_KEY"),
+            PROMPT_MODEL: get_env_or("SERVICE_PROMPT_MODEL", "llama3.1:latest"),
+            LLM_ALLOWED_MODELS: get_env_list("SERVICE_LLM_ALLOWED_MODELS"),
+
+            EMBED_PROVIDER: embed_provider.clone(),
+            EMBED_BASE_URL: get_env_opt("SERVICE_EMBED_BASE_URL"),
+            EMBED_API_KEY: get_env_opt("SERVICE_EMBED_API_KEY"),
+            EMBED_MODEL: get_env_or("SERVICE_EMBED_MODEL", default_embed_model(&embed_provider)),
+            EMBED_DIMENSIONS: get_env_opt("SERVICE_EMBED_DIMENSIONS")
+                .map(|v| v.parse().map_err(|_| Error::ConfigWrongFormat("SERVICE_EMBED_DIMENSIONS")))
+                .transpose()?,
+            SPARSE_EMBED_MODEL: sparse_embed_model,
+            FASTEMBED_CACHE_DIR: get_env_opt("SERVICE_FASTEMBED_CACHE_DIR"),
+
+            RETRIEVAL_MODE: get_env_parse_or("SERVICE_RETRIEVAL_MODE", default_retrieval_mode)?,
+            RETRIEVAL_TOP_K: get_env_parse_or("SERVICE_RETRIEVAL_TOP_K", 10)?,
+            RETRIEVAL_VECTORS: match get_env_list("SERVICE_RETRIEVAL_VECTORS") {
+                vectors if vectors.is_empty() => vec!["combined".to_string()],
+                vectors => {
+                    // a typo would otherwise leave fewer vectors, or none, to search
+                    for vector in &vectors {
+                        vector.parse::<NamedVector>()?;
+                    }
+                    vectors
+                }
+            },
+            HYBRID_DENSE_WEIGHT: get_env_parse_or("SERVICE_HYBRID_DENSE_WEIGHT", 1.0)?,
+            HYBRID_SPARSE_WEIGHT: get_env_parse_or("SERVICE_HYBRID_SPARSE_WEIGHT", 1.0)?,
+            RERANK_MODEL: get_env_opt("SERVICE_RERANK_MODEL"),
+            RERANK_CANDIDATES: get_env_parse_or("SERVICE_RERANK_CANDIDATES", 50)?,
+            RERANK_TOP_N: get_env_parse_or("SERVICE_RERANK_TOP_N", 5)?,
+            MIN_RETRIEVAL_SCORE: get_env_opt("SERVICE_MIN_RETRIEVAL_SCORE")
+                .map(|v| v.parse().map_err(|_| Error::ConfigWrongFormat("SERVICE_MIN_RETRIEVAL_SCORE")))
+                .transpose()?,
+
+            GROUNDEDNESS_CHECK: get_env_parse_or("SERVICE_GROUNDEDNESS_CHECK", false)?,
+            GROUNDEDNESS_THRESHOLD: get_env_parse_or("SERVICE_GROUNDEDNESS_THRESHOLD", 0.5)?,
+            NO_ANSWER_MESSAGE: get_env_or(
+                "SERVICE_NO_ANSWER_MESSAGE",
+                "The knowledge base does not cover this question.",
+            ),
+
+            PIPELINE_PRESETS: get_env_opt("SERVICE_PIPELINE_PRESETS")
+                .map(|json| pipeline::parse_presets(&json)
+                    .map_err(|_| Error::ConfigWrongFormat("SERVICE_PIPELINE_PRESETS")))
+                .transpose()?
+                .unwrap_or_default(),
+            DEFAULT_PIPELINE: get_env_or("SERVICE_DEFAULT_PIPELINE", pipeline::DEFAULT_PRESET),
+            MULTI_QUERY_VARIANTS: get_env_parse_or("SERVICE_MULTI_QUERY_VARIANTS", 3)?,
+
+            ANSWER_CACHE_TTL_SECS: get_env_parse_or("SERVICE_ANSWER_CACHE_TTL_SECS", 3600)?,
+            ANSWER_CACHE_SIMILARITY: get_env_opt("SERVICE_ANSWER_CACHE_SIMILARITY")
+                .map(|v| v.parse().map_err(|_| Error::ConfigWrongFormat("SERVICE_ANSWER_CACHE_SIMILARITY")))
+                .transpose()?,
+            ANSWER_CACHE_PER_USER: get_env_parse_or("SERVICE_ANSWER_CACHE_PER_USER", false)?,
+
+            CHAT_HISTORY_TOKENS: get_env_parse_or("SERVICE_CHAT_HISTORY_TOKENS", 4096)?,
+            CHAT_AUTO_TITLE: get_env_parse_or("SERVICE_CHAT_AUTO_TITLE", true)?,
+            CHAT_SHARE_TTL_HOURS: get_env_parse_or("SERVICE_CHAT_SHARE_TTL_HOURS", 168)?,
+            AGENT_MAX_STEPS: get_env_parse_or("SERVICE_AGENT_MAX_STEPS", 8)?,
         })
     }
 }
 
+fn default_embed_model(provider: &str) -> &'static str {
+    if provider.eq_ignore_ascii_case("fastembed") {
+        "BAAI/bge-small-en-v1.5"
+    } else {
+        "bge-m3:latest"
+    }
+}
+
 fn get_env(name: &'static str) -> Result<String> {
     env::var(name).map_err(|_| Error::ConfigMissingEnv(name))
 }
 
+fn get_env_opt(name: &'static str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn get_env_or(name: &'static str, default: &str) -> String {
+    get_env_opt(name).unwrap_or_else(|| default.to_string())
+}
+
+/// Comma separated list, empty when the variable is not set.
+fn get_env_list(name: &'static str) -> Vec<String> {
+    get_env_opt(name)
+        .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default()
+}
+
 fn get_env_parse<T: FromStr>(name: &'static str) -> Result<T> {
     let val = get_env(name)?;
     val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name))
 }
 
+fn get_env_parse_or<T: FromStr>(name: &'static str, default: T) -> Result<T> {
+    match get_env_opt(name) {
+        Some(val) => val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name)),
+        None => Ok(default),
+    }
+}
+
 fn get_env_b64u_as_u8s(name: &'static str) -> Result<Vec<u8>> {
     base64_url::decode(&get_env(name)?).map_err(|_| Error::ConfigWrongFormat(name))
 }
@@ -11,6 +11,7 @@ use crate::{crypt, web};
 
 use swiftide::integrations::qdrant::VectorConfigBuilderError;
 use swiftide::integrations::ollama::OllamaBuilderError;
+use swiftide::integrations::openai::OpenAIBuilderError;
 use swiftide::integrations::redis::RedisBuilderError;
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -38,6 +39,26 @@ pub enum Error {
     OllamaError(String),
     RedisError(String),
 
+    LlmError(String),
+    LlmProviderUnknown(String),
+    LlmModelNotAllowed(String),
+
+    RetrievalModeUnavailable(String),
+    RetrievalParamsInvalid,
+
+    PipelineConfigInvalid(String),
+    PipelinePresetNotFound(String),
+
+    PromptTemplateNotFound(String),
+    PromptTemplateInvalid(String),
+
+    FeedbackInvalid(String),
+    ChatMessageInvalid(String),
+    ConversationInvalid(String),
+    ChatImportInvalid(String),
+    ActionItemInvalid(String),
+    StatisticsRangeInvalid(String),
+
     EntityNotFound { entity: &'static str, id: i64 },
 
     CryptError(String),
@@ -80,6 +101,12 @@ impl From<OllamaBuilderError> for Error {
     }
 }
 
+impl From<OpenAIBuilderError> for Error {
+    fn from(err: OpenAIBuilderError) -> Self {
+        Error::LlmError(err.to_string())
+    }
+}
+
 impl From<RedisBuilderError> for Error {
     fn from(err: RedisBuilderError) -> Self {
         Error::RedisError(err.to_string())
@@ -175,6 +202,71 @@ impl Error {
                 (StatusCode::INTERNAL_SERVER_ERROR, ClientError::CLIENT_ERROR)
             }
 
+            Self::LlmError(_) => {
+                error!("LLM client error: {:?}", self);
+                (StatusCode::INTERNAL_SERVER_ERROR, ClientError::CLIENT_ERROR)
+            }
+            Self::LlmProviderUnknown(_) => {
+                error!("Unknown LLM provider: {:?}", self);
+                (StatusCode::INTERNAL_SERVER_ERROR, ClientError::CONFIG_ERROR)
+            }
+            Self::LlmModelNotAllowed(_) => {
+                warn!("LLM model not allowed: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+
+            Self::RetrievalModeUnavailable(_) => {
+                warn!("Retrieval stage has no model configured: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::RetrievalParamsInvalid => {
+                warn!("Invalid retrieval parameters: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+
+            Self::PipelineConfigInvalid(_) => {
+                warn!("Invalid pipeline config: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::PipelinePresetNotFound(_) => {
+                warn!("Unknown pipeline preset: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+
+            Self::PromptTemplateNotFound(_) => {
+                warn!("Prompt template not found: {:?}", self);
+                (StatusCode::NOT_FOUND, ClientError::ENTITY_NOT_FOUND)
+            }
+            Self::PromptTemplateInvalid(_) => {
+                warn!("Invalid prompt template: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+
+            Self::FeedbackInvalid(_) => {
+                warn!("Invalid feedback: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::ChatMessageInvalid(_) => {
+                warn!("Invalid chat message operation: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::ConversationInvalid(_) => {
+                warn!("Invalid conversation: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::ChatImportInvalid(_) => {
+                warn!("Invalid conversation import: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::ActionItemInvalid(_) => {
+                warn!("Invalid action item: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+            Self::StatisticsRangeInvalid(_) => {
+                warn!("Invalid statistics range: {:?}", self);
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
+            }
+
 
             Self::EntityNotFound { entity, id } => {
                 warn!("Entity not found: {:?} with id: {:?}", entity, id);
@@ -10,6 +10,7 @@ use uuid::Uuid;
 
 pub async fn log_request(
     uuid: Uuid,
+    trace_id: Option<String>,
     req_method: Method,
     uri: Uri,
     ctx: Option<Ctx>,
@@ -28,6 +29,7 @@ pub async fn log_request(
 
     let log_line = RequestLogLine {
         uuid: uuid.to_string(),
+        trace_id,
         timestamp: timestamp.to_string(),
 
         req_path: uri.to_string(),
@@ -50,6 +52,7 @@ pub async fn log_request(
 #[derive(Serialize)]
 struct RequestLogLine {
     uuid: String,
+    trace_id: Option<String>,
     timestamp: String,
 
     user_id: Option<i64>,
@@ -6,11 +6,11 @@ use crate::ctx::Ctx;
 use crate::log::log_request;
 use crate::model::manager::ModelManager;
 use axum::extract::{Path, Query};
-use axum::http::{header, HeaderValue, Method, Uri};
+use axum::http::{header, HeaderName, HeaderValue, Method, Uri};
 use axum::response::{Html, IntoResponse, Response};
 use axum::routing::{get, get_service};
 use axum::{middleware, Json, Router};
-use axum_prometheus::PrometheusMetricLayer;
+use axum_prometheus::PrometheusMetricLayerBuilder;
 use opentelemetry::global;
 use opentelemetry::sdk::propagation::TraceContextPropagator;
 use serde::Deserialize;
@@ -30,6 +30,7 @@ pub mod model;
 
 pub mod web;
 mod open_telemetry;
+mod metrics;
 pub mod dev_utils;
 mod config;
 
@@ -47,7 +48,9 @@ async fn main() -> Result<()> {
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
 
-    let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
+    let (prometheus_layer, metric_handle) = PrometheusMetricLayerBuilder::new()
+        .with_metrics_from_fn(metrics::install_recorder)
+        .build_pair();
     let metric_handle = Arc::new(metric_handle);
     let config = config();
 
@@ -62,10 +65,15 @@ async fn main() -> Result<()> {
         .merge(web::routes_query_data::routes(mm.clone()))
         .merge(web::routes_fine_tune::routes(mm.clone()))
         .merge(web::routes_chat::routes(mm.clone()))
+        .merge(web::routes_chat_ws::routes(mm.clone()))
         .route_layer(middleware::from_fn(web::mw_auth::mw_require_auth));
 
     let routes_admin = Router::new()
         .merge(web::routes_statistics::routes(mm.clone()))
+        .merge(web::routes_retrieval::routes(mm.clone()))
+        .merge(web::routes_eval::routes(mm.clone()))
+        .merge(web::routes_prompt::routes(mm.clone()))
+        .merge(web::routes_feedback::routes(mm.clone()))
         .route(
             "/metrics",
             get({
@@ -94,6 +102,7 @@ async fn main() -> Result<()> {
         .merge(routes_hello())
         .merge(web::routes_register::routes(mm.clone()))
         .merge(web::routes_login::routes(mm.clone()))
+        .merge(web::routes_share::routes(mm.clone()))
         .nest("/api", routes_apis)
         .nest("/admin", routes_admin)
         .merge(routes_metrics)
@@ -104,6 +113,7 @@ async fn main() -> Result<()> {
         ))
         .layer(CookieManagerLayer::new())
         .layer(prometheus_layer)
+        .layer(middleware::from_fn(web::mw_trace::mw_trace_context))
         .fallback_service(routes_static());
 
     let cors = CorsLayer::new()
@@ -119,6 +129,8 @@ async fn main() -> Result<()> {
             header::CONTENT_TYPE,         // JSON POSTs from the browser
             header::ACCEPT,               // fetch default
             header::AUTHORIZATION,        // if you ever add bearer tokens
+            HeaderName::from_static("traceparent"), // W3C trace context
+            HeaderName::from_static("tracestate"),
         ])
         .allow_credentials(true);
 
@@ -141,6 +153,7 @@ async fn main_response_mapper(
 ) -> Response {
     println!("->> {:<12} - main_response_mapper", "RES_MAPPER");
     let uuid = Uuid::new_v4();
+    let trace_id = open_telemetry::current_trace_id();
 
     let service_error = res.extensions().get::<Error>();
     let client_status_error = service_error.map(|se| se.client_status_and_error());
@@ -153,6 +166,7 @@ async fn main_response_mapper(
                     "error": {
                         "type": client_error.as_ref(),
                         "req_uuid": uuid.to_string(),
+                        "trace_id": trace_id,
                     }
                 });
 
@@ -163,7 +177,7 @@ async fn main_response_mapper(
 
     let client_error = client_status_error.unzip().1;
     let _ =
-        log_request(uuid, req_method, uri, ctx, service_error, client_error).await;
+        log_request(uuid, trace_id, req_method, uri, ctx, service_error, client_error).await;
 
     println!();
     error_response.unwrap_or(res)
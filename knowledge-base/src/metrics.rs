@@ -0,0 +1,136 @@
+use axum_prometheus::{utils::SECONDS_DURATION_BUCKETS, AXUM_HTTP_REQUESTS_DURATION_SECONDS};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Duration;
+
+// -- Metric names
+pub const RAG_STAGE_DURATION_SECONDS: &str = "kb_rag_stage_duration_seconds";
+pub const RAG_PIPELINE_DURATION_SECONDS: &str = "kb_rag_pipeline_duration_seconds";
+pub const LLM_ERRORS_TOTAL: &str = "kb_llm_errors_total";
+pub const LLM_TOKENS_TOTAL: &str = "kb_llm_tokens_total";
+pub const INGEST_DURATION_SECONDS: &str = "kb_ingest_duration_seconds";
+pub const INGEST_CHUNKS_INDEXED_TOTAL: &str = "kb_ingest_chunks_indexed_total";
+pub const INGEST_CACHE_HITS_TOTAL: &str = "kb_ingest_cache_hits_total";
+pub const INGEST_CACHE_MISSES_TOTAL: &str = "kb_ingest_cache_misses_total";
+pub const INGEST_JOBS_IN_PROGRESS: &str = "kb_ingest_jobs_in_progress";
+pub const ANSWER_CACHE_HITS_TOTAL: &str = "kb_answer_cache_hits_total";
+pub const ANSWER_CACHE_MISSES_TOTAL: &str = "kb_answer_cache_misses_total";
+
+/// LLM calls run from hundreds of milliseconds to minutes, far beyond the
+/// HTTP buckets axum-prometheus ships with.
+const LLM_DURATION_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Installs the global recorder shared by the HTTP layer and our own metrics.
+pub fn install_recorder() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(AXUM_HTTP_REQUESTS_DURATION_SECONDS.to_string()),
+            SECONDS_DURATION_BUCKETS,
+        )
+        .and_then(|b| b.set_buckets_for_metric(
+            Matcher::Suffix("_duration_seconds".to_string()),
+            LLM_DURATION_BUCKETS,
+        ))
+        .and_then(|b| b.install_recorder())
+        .unwrap_or_else(|ex| panic!("FATAL - WHILE INSTALLING METRICS RECORDER - Cause: {ex:?}"))
+}
+
+pub fn record_stage(pipeline: &str, model: &str, stage: &str, elapsed: Duration, ok: bool) {
+    histogram!(
+        RAG_STAGE_DURATION_SECONDS,
+        "pipeline" => pipeline.to_string(),
+        "model" => model.to_string(),
+        "stage" => stage.to_string(),
+    )
+    .record(elapsed.as_secs_f64());
+
+    if !ok {
+        record_llm_error(pipeline, model, stage);
+    }
+}
+
+pub fn record_pipeline(pipeline: &str, model: &str, elapsed: Duration) {
+    histogram!(
+        RAG_PIPELINE_DURATION_SECONDS,
+        "pipeline" => pipeline.to_string(),
+        "model" => model.to_string(),
+    )
+    .record(elapsed.as_secs_f64());
+}
+
+pub fn record_llm_error(pipeline: &str, model: &str, stage: &str) {
+    counter!(
+        LLM_ERRORS_TOTAL,
+        "pipeline" => pipeline.to_string(),
+        "model" => model.to_string(),
+        "stage" => stage.to_string(),
+    )
+    .increment(1);
+}
+
+/// `kind` is either `prompt` or `completion`.
+pub fn record_tokens(pipeline: &str, model: &str, stage: &str, kind: &'static str, tokens: i32) {
+    if tokens <= 0 {
+        return;
+    }
+    counter!(
+        LLM_TOKENS_TOTAL,
+        "pipeline" => pipeline.to_string(),
+        "model" => model.to_string(),
+        "stage" => stage.to_string(),
+        "kind" => kind,
+    )
+    .increment(tokens as u64);
+}
+
+pub fn record_ingest(model: &str, elapsed: Duration, ok: bool) {
+    histogram!(
+        INGEST_DURATION_SECONDS,
+        "pipeline" => "ingest_document",
+        "model" => model.to_string(),
+        "status" => if ok { "ok" } else { "error" },
+    )
+    .record(elapsed.as_secs_f64());
+
+    if !ok {
+        record_llm_error("ingest_document", model, "run");
+    }
+}
+
+pub fn record_chunk_indexed(model: &str) {
+    counter!(INGEST_CHUNKS_INDEXED_TOTAL, "pipeline" => "ingest_document", "model" => model.to_string())
+        .increment(1);
+}
+
+pub fn record_cache_lookup(hit: bool) {
+    let name = if hit { INGEST_CACHE_HITS_TOTAL } else { INGEST_CACHE_MISSES_TOTAL };
+    counter!(name, "pipeline" => "ingest_document").increment(1);
+}
+
+/// `kind` is `exact` or `similar`.
+pub fn record_answer_cache_hit(pipeline: &str, kind: &'static str) {
+    counter!(ANSWER_CACHE_HITS_TOTAL, "pipeline" => pipeline.to_string(), "match" => kind).increment(1);
+}
+
+pub fn record_answer_cache_miss(pipeline: &str) {
+    counter!(ANSWER_CACHE_MISSES_TOTAL, "pipeline" => pipeline.to_string()).increment(1);
+}
+
+/// Counts a running ingestion in `kb_ingest_jobs_in_progress`, even when it
+/// bails out early.
+pub struct IngestJobGuard;
+
+impl IngestJobGuard {
+    pub fn enter() -> Self {
+        gauge!(INGEST_JOBS_IN_PROGRESS, "pipeline" => "ingest_document").increment(1.0);
+        IngestJobGuard
+    }
+}
+
+impl Drop for IngestJobGuard {
+    fn drop(&mut self) {
+        gauge!(INGEST_JOBS_IN_PROGRESS, "pipeline" => "ingest_document").decrement(1.0);
+    }
+}
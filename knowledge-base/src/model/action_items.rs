@@ -0,0 +1,253 @@
+//! src/model/action_items.rs
+//! action items extracted from a document or a conversation by the prompt
+//! model, proposed to the user and, once accepted, stored as tasks that
+//! point back at where they came from
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use lazy_regex::regex;
+use serde::{Deserialize, Serialize};
+use swiftide::prompt::Prompt;
+use swiftide::traits::SimplePrompt;
+
+use crate::metrics;
+use crate::model::chat::{ConversationBmc, Message, MessageBmc};
+use crate::model::documents::DocumentBmc;
+use crate::model::llm::LlmClient;
+use crate::model::manager::ModelManager;
+use crate::model::prompt::PromptSet;
+use crate::model::task::{Task, TaskForCreateInternal};
+use crate::{Ctx, Error, Result};
+
+const PIPELINE: &str = "action_items";
+
+pub const EXTRACT_PROMPT: &str = "Extract the action items from the text below: things someone \
+has to do, follow-ups and deadlines. Write one per line as `- <task> | <reference>`, where the task \
+is a short imperative sentence and the reference is {{ reference }}. Write NONE if there are none.\n\n\
+Text:\n{{ text }}";
+
+/// Characters of a document sent to the model; the rest is not read.
+const DOCUMENT_CHARS: usize = 24_000;
+/// Tokens of the conversation sent to the model, newest messages first. The
+/// newest message is always sent, cut to this size if need be.
+const CONVERSATION_TOKENS: i32 = 8_192;
+/// Same as the `task.title` column.
+const MAX_TITLE_CHARS: usize = 256;
+const MAX_QUOTE_CHARS: usize = 500;
+const MAX_ITEMS: usize = 50;
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Data structures                                                          */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+/// Where an action item was found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskSource {
+    Document { document_id: i64 },
+    Message { conversation_id: i64, message_id: i64 },
+}
+
+/// Action item the user can accept as a task, as is or edited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedTask {
+    pub title: String,
+    pub source: TaskSource,
+    /// Words of the source the item was taken from.
+    #[serde(default)]
+    pub quote: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActionItems {
+    pub proposals: Vec<ProposedTask>,
+    /// Only the beginning of the document, or the most recent messages,
+    /// were read.
+    pub truncated: bool,
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  BMC                                                                      */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub struct ActionItemBmc;
+
+impl ActionItemBmc {
+    /// Action items of a document. Nothing is stored.
+    pub async fn from_document(ctx: &Ctx, mm: &ModelManager, doc_id: i64, model: Option<&str>) -> Result<ActionItems> {
+        let llm = mm.llm.prompt_for(model)?;
+        let (_, text) = DocumentBmc::read_text(ctx, mm, doc_id).await?;
+        let truncated = text.chars().count() > DOCUMENT_CHARS;
+        let text: String = text.chars().take(DOCUMENT_CHARS).collect();
+
+        let reply = extract(ctx, mm, &llm, &text, "a short verbatim quote of the text it comes from").await?;
+        let proposals = parse_items(&reply)
+            .into_iter()
+            .map(|(title, reference)| ProposedTask {
+                title,
+                source: TaskSource::Document { document_id: doc_id },
+                quote: reference.map(|q| q.chars().take(MAX_QUOTE_CHARS).collect()),
+            })
+            .collect();
+        Ok(ActionItems { proposals, truncated })
+    }
+
+    /// Action items of the active branch of a conversation the user can
+    /// read. Nothing is stored.
+    pub async fn from_conversation(ctx: &Ctx, mm: &ModelManager, conv_id: i64, model: Option<&str>) -> Result<ActionItems> {
+        let conv = ConversationBmc::get(ctx, mm, conv_id).await?;
+        let llm = mm.llm.prompt_for(model.or(conv.model.as_deref()))?;
+        let branch = MessageBmc::active_path(ctx, mm, conv_id, i32::MAX).await?;
+        let (mut messages, truncated) = within_budget(branch);
+        messages.reverse();
+        let Some(last) = messages.last().map(|m| m.id) else {
+            return Err(Error::ConversationInvalid("the conversation has no messages".to_string()));
+        };
+
+        let reply = extract(ctx, mm, &llm, &transcript(&messages), "the #id of the message it comes from").await?;
+        let ids: HashSet<i64> = messages.iter().map(|m| m.id).collect();
+        let proposals = parse_items(&reply)
+            .into_iter()
+            .map(|(title, reference)| {
+                // items the model could not place are attributed to the latest message
+                let message_id = reference
+                    .and_then(|r| r.trim_start_matches('#').parse().ok())
+                    .filter(|id| ids.contains(id))
+                    .unwrap_or(last);
+                ProposedTask {
+                    title,
+                    source: TaskSource::Message { conversation_id: conv_id, message_id },
+                    quote: None,
+                }
+            })
+            .collect();
+        Ok(ActionItems { proposals, truncated })
+    }
+
+    /// Stores accepted action items as tasks of the caller, all or none.
+    /// Every source is checked first: documents must exist, messages must be
+    /// readable by the caller.
+    pub async fn accept(ctx: &Ctx, mm: &ModelManager, proposals: Vec<ProposedTask>) -> Result<Vec<Task>> {
+        if proposals.is_empty() {
+            return Err(Error::ActionItemInvalid("no action items to accept".to_string()));
+        }
+        let mut tasks_c = Vec::with_capacity(proposals.len());
+        for proposal in proposals {
+            let title = proposal.title.trim();
+            if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+                return Err(Error::ActionItemInvalid(format!(
+                    "task titles must have 1 to {MAX_TITLE_CHARS} characters"
+                )));
+            }
+            let mut task_c = TaskForCreateInternal {
+                title: title.to_string(),
+                created_by: ctx.user_id(),
+                source_quote: proposal.quote.filter(|q| !q.trim().is_empty()),
+                ..Default::default()
+            };
+            match proposal.source {
+                TaskSource::Document { document_id } => {
+                    DocumentBmc::get(ctx, mm, document_id).await?;
+                    task_c.source_document_id = Some(document_id);
+                }
+                TaskSource::Message { conversation_id, message_id } => {
+                    MessageBmc::get_in(ctx, mm, conversation_id, message_id).await?;
+                    task_c.source_message_id = Some(message_id);
+                }
+            }
+            tasks_c.push(task_c);
+        }
+
+        let mut tx = mm.db().begin().await?;
+        let mut tasks = Vec::with_capacity(tasks_c.len());
+        for task_c in tasks_c {
+            let task = sqlx::query_as::<_, Task>(
+                "INSERT INTO task (title, created_by, source_document_id, source_message_id, source_quote) \
+                 VALUES ($1, $2, $3, $4, $5) \
+                 RETURNING id, title, created_by, source_document_id, source_message_id, source_quote"
+            )
+                .bind(task_c.title)
+                .bind(task_c.created_by)
+                .bind(task_c.source_document_id)
+                .bind(task_c.source_message_id)
+                .bind(task_c.source_quote)
+                .fetch_one(&mut *tx)
+                .await?;
+            tasks.push(task);
+        }
+        tx.commit().await?;
+        Ok(tasks)
+    }
+}
+
+async fn extract(ctx: &Ctx, mm: &ModelManager, llm: &LlmClient, text: &str, reference: &str) -> Result<String> {
+    let prompts = PromptSet::load(ctx, mm).await?;
+    let prompt = Prompt::from(prompts.get("action_items").unwrap_or(EXTRACT_PROMPT).to_string())
+        .with_context_value("text", text)
+        .with_context_value("reference", reference);
+    let start = Instant::now();
+    let reply = llm.prompt(prompt).await;
+    let elapsed = start.elapsed();
+    metrics::record_stage(PIPELINE, llm.prompt_model(), "extract", elapsed, reply.is_ok());
+    mm.log_pipeline_run(ctx, PIPELINE, elapsed.as_millis() as i32, reply.is_ok()).await;
+
+    reply.map_err(|e| Error::LlmError(e.to_string()))
+}
+
+/// Newest messages of a branch (given newest first) that fit in
+/// `CONVERSATION_TOKENS`, and whether anything was left out or cut.
+fn within_budget(branch: Vec<Message>) -> (Vec<Message>, bool) {
+    let complete = branch.last().is_none_or(|m| m.parent_id.is_none());
+    let mut kept = Vec::new();
+    let mut tokens = 0;
+    for mut message in branch {
+        if tokens + message.token_count > CONVERSATION_TOKENS {
+            if kept.is_empty() {
+                // too long on its own: send its beginning
+                let chars = message.content.chars().count();
+                let keep = chars * CONVERSATION_TOKENS as usize / message.token_count.max(1) as usize;
+                message.content = message.content.chars().take(keep).collect();
+                kept.push(message);
+            }
+            return (kept, true);
+        }
+        tokens += message.token_count;
+        kept.push(message);
+    }
+    (kept, !complete)
+}
+
+fn transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let role = if m.sender == "user" { "User" } else { "Assistant" };
+            format!("[#{}] {role}: {}\n", m.id, m.content)
+        })
+        .collect()
+}
+
+/// `(title, reference)` of every item line of the reply, without list
+/// markers and duplicates.
+fn parse_items(reply: &str) -> Vec<(String, Option<String>)> {
+    let mut seen = HashSet::new();
+    reply
+        .lines()
+        .filter_map(|line| {
+            let line = regex!(r"^\s*(?:[-*•]|\d+[.)])\s*").replace(line, "");
+            let line = line.trim();
+            if line.is_empty() || line.eq_ignore_ascii_case("none") {
+                return None;
+            }
+            let (title, reference) = match line.rsplit_once('|') {
+                Some((title, reference)) => (title, Some(reference.trim().trim_matches('"').trim().to_string())),
+                None => (line, None),
+            };
+            let title: String = title.trim().trim_matches('`').chars().take(MAX_TITLE_CHARS).collect();
+            (!title.is_empty() && seen.insert(title.to_lowercase()))
+                .then(|| (title, reference.filter(|r| !r.is_empty())))
+        })
+        .take(MAX_ITEMS)
+        .collect()
+}
@@ -0,0 +1,475 @@
+//! src/model/agent.rs
+//! agent mode: the prompt model answers by calling tools (knowledge base
+//! search, documents, the caller's tasks, arithmetic) as often as it needs,
+//! up to `SERVICE_AGENT_MAX_STEPS` calls
+
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::config;
+use crate::metrics;
+use crate::model::chat_events::Progress;
+use crate::model::documents::DocumentBmc;
+use crate::model::llm::{LlmClient, LlmMessage, ToolCall, ToolSpec};
+use crate::model::manager::{AnswerWithSources, ChatTurn, ModelManager};
+use crate::model::prompt::PromptSet;
+use crate::model::retrieval::{QdrantRetriever, RetrievalParams, RetrievedDocument};
+use crate::model::task::{TaskBmc, TaskForCreateInternal};
+use crate::{Ctx, Error, Result};
+
+pub const AGENT_PROMPT: &str = "You answer questions about an internal knowledge base and help \
+the user with their tasks. Use the tools: search the knowledge base before answering questions \
+about its content, read a whole document when the search results are not enough, and use the \
+calculator for arithmetic. Cite the documents you used by name. If the tools do not turn up an \
+answer, say so.";
+
+/// Characters of a document returned by `get_document`.
+const DOCUMENT_CHARS: usize = 12_000;
+/// Chunks a search returns at most.
+const MAX_SEARCH_RESULTS: u64 = 20;
+
+/// One tool call of an agent-mode answer, stored with the message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub tool: String,
+    pub arguments: Value,
+    #[serde(default)]
+    pub result: Option<Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+fn tools() -> Vec<ToolSpec> {
+    vec![
+        ToolSpec {
+            name: "search_knowledge_base",
+            description: "Semantic search over the indexed documents. Returns the best matching \
+                          chunks with their document id and name.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What to look for" },
+                    "doc_ids": { "type": "array", "items": { "type": "string" }, "description": "Only search these documents" },
+                    "doc_names": { "type": "array", "items": { "type": "string" }, "description": "Only search documents with these file names" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_SEARCH_RESULTS }
+                },
+                "required": ["query"]
+            }),
+        },
+        ToolSpec {
+            name: "get_document",
+            description: "Full text of a document by id, truncated when long.",
+            parameters: json!({
+                "type": "object",
+                "properties": { "id": { "type": "integer" } },
+                "required": ["id"]
+            }),
+        },
+        ToolSpec {
+            name: "list_tasks",
+            description: "The user's tasks.",
+            parameters: json!({ "type": "object", "properties": {} }),
+        },
+        ToolSpec {
+            name: "create_task",
+            description: "Adds a task to the user's list. Only when the user asks for it.",
+            parameters: json!({
+                "type": "object",
+                "properties": { "title": { "type": "string" } },
+                "required": ["title"]
+            }),
+        },
+        ToolSpec {
+            name: "calculate",
+            description: "Evaluates an arithmetic expression with + - * / % ^ and parentheses.",
+            parameters: json!({
+                "type": "object",
+                "properties": { "expression": { "type": "string" } },
+                "required": ["expression"]
+            }),
+        },
+    ]
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    query: String,
+    #[serde(default)]
+    doc_ids: Vec<String>,
+    #[serde(default)]
+    doc_names: Vec<String>,
+    limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct GetDocumentArgs {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct CreateTaskArgs {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct CalculateArgs {
+    expression: String,
+}
+
+/// Answers `prompt` with the tool loop. Tools act as the caller: tasks are
+/// the caller's own and created in their name.
+pub async fn answer(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    llm: &LlmClient,
+    prompt: &str,
+    turn: ChatTurn<'_>,
+    retrieval: &RetrievalParams,
+    prompts: &PromptSet,
+) -> Result<AnswerWithSources> {
+    let tools = tools();
+    let mut messages = vec![LlmMessage::System(prompts.get("agent").unwrap_or(AGENT_PROMPT).to_string())];
+    if let Some(history) = turn.history {
+        messages.push(LlmMessage::System(format!("Conversation so far:\n{history}")));
+    }
+    messages.push(LlmMessage::User(prompt.to_string()));
+
+    let mut agent = Agent { ctx, mm, retrieval, progress: turn.progress, answer: empty_answer() };
+    let max_steps = config().AGENT_MAX_STEPS;
+    let reply = loop {
+        // out of steps: the model has to answer with what it has
+        let offered = if agent.answer.agent_steps.len() < max_steps { tools.as_slice() } else { &[] };
+        if let Some(progress) = turn.progress {
+            progress.stage("answer");
+        }
+        let start = Instant::now();
+        let reply = llm.chat(&messages, offered).await;
+        metrics::record_stage("chat", llm.prompt_model(), "answer", start.elapsed(), reply.is_ok());
+        let reply = reply.map_err(|e| Error::LlmError(e.to_string()))?;
+        if reply.tool_calls.is_empty() || offered.is_empty() {
+            break reply.content.unwrap_or_default();
+        }
+
+        messages.push(LlmMessage::Assistant { content: reply.content, tool_calls: reply.tool_calls.clone() });
+        for call in &reply.tool_calls {
+            let content = match agent.answer.agent_steps.len() < max_steps {
+                true => agent.call(&tools, llm.prompt_model(), call).await,
+                false => json!({ "error": "step limit reached" }).to_string(),
+            };
+            messages.push(LlmMessage::Tool { call_id: call.id.clone(), content });
+        }
+    };
+
+    let mut answer = agent.answer;
+    answer.fallback = reply.trim().is_empty();
+    answer.answer = match answer.fallback {
+        true => config().NO_ANSWER_MESSAGE.clone(),
+        false => reply,
+    };
+    if let Some(progress) = turn.progress {
+        progress.token(&answer.answer);
+    }
+    Ok(answer)
+}
+
+fn empty_answer() -> AnswerWithSources {
+    AnswerWithSources {
+        answer: String::new(),
+        documents: Vec::new(),
+        contexts: Vec::new(),
+        confidence: None,
+        grounded: None,
+        fallback: false,
+        pipeline: None,
+        agent_steps: Vec::new(),
+    }
+}
+
+struct Agent<'a> {
+    ctx: &'a Ctx,
+    mm: &'a ModelManager,
+    retrieval: &'a RetrievalParams,
+    progress: Option<&'a Progress>,
+    /// Sources found and steps taken so far.
+    answer: AnswerWithSources,
+}
+
+impl Agent<'_> {
+    /// Runs a tool call and records it as a step. Failures go back to the
+    /// model as the tool's result, so it can correct itself.
+    async fn call(&mut self, tools: &[ToolSpec], model: &str, call: &ToolCall) -> String {
+        let arguments: Value = serde_json::from_str(&call.arguments).unwrap_or(Value::String(call.arguments.clone()));
+        let start = Instant::now();
+        let result = match tools.iter().find(|t| t.name == call.name) {
+            Some(tool) => {
+                if let Some(progress) = self.progress {
+                    progress.stage(tool.name);
+                }
+                let result = self.run(tool.name, &call.arguments).await;
+                metrics::record_stage("chat", model, tool.name, start.elapsed(), result.is_ok());
+                result
+            }
+            None => Err(format!("unknown tool `{}`", call.name)),
+        };
+
+        let (content, step_result, error) = match result {
+            Ok(value) => (value.to_string(), Some(value), None),
+            Err(error) => (json!({ "error": error }).to_string(), None, Some(error)),
+        };
+        self.answer.agent_steps.push(AgentStep {
+            tool: call.name.clone(),
+            arguments,
+            result: step_result,
+            error,
+            duration_ms: start.elapsed().as_millis() as u64,
+        });
+        content
+    }
+
+    async fn run(&mut self, tool: &str, arguments: &str) -> core::result::Result<Value, String> {
+        let client_error = |e: Error| e.client_status_and_error().1.as_ref().to_string();
+        match tool {
+            "search_knowledge_base" => {
+                let args: SearchArgs = parse_args(arguments)?;
+                let mut retrieval = self.retrieval.clone();
+                if let Some(limit) = args.limit {
+                    let limit = limit.clamp(1, MAX_SEARCH_RESULTS);
+                    retrieval.top_k = Some(limit);
+                    retrieval.top_n = Some(limit as usize);
+                }
+                let retriever = QdrantRetriever::new(self.mm.qdrant.clone()).with_documents(&args.doc_ids, &args.doc_names);
+                let chunks = self.mm
+                    .search_chunks(&args.query, &retrieval, retriever)
+                    .await
+                    .map_err(client_error)?;
+                let mut results = Vec::new();
+                for chunk in &chunks {
+                    let source = RetrievedDocument::from(chunk);
+                    results.push(json!({
+                        "doc_id": source.doc_id,
+                        "doc_name": source.doc_name,
+                        "title": source.title,
+                        "text": chunk.content(),
+                    }));
+                    if !self.answer.contexts.iter().any(|c| c == chunk.content()) {
+                        self.answer.contexts.push(chunk.content().to_string());
+                        self.answer.documents.push(source);
+                    }
+                }
+                Ok(Value::Array(results))
+            }
+            "get_document" => {
+                let args: GetDocumentArgs = parse_args(arguments)?;
+                let (document, text) = DocumentBmc::read_text(self.ctx, self.mm, args.id).await.map_err(client_error)?;
+                let truncated = text.chars().count() > DOCUMENT_CHARS;
+                Ok(json!({
+                    "id": document.id,
+                    "filename": document.filename,
+                    "text": text.chars().take(DOCUMENT_CHARS).collect::<String>(),
+                    "truncated": truncated,
+                }))
+            }
+            "list_tasks" => {
+                let tasks = TaskBmc::list_for_user(self.ctx, self.mm).await.map_err(client_error)?;
+                Ok(json!(tasks))
+            }
+            "create_task" => {
+                let args: CreateTaskArgs = parse_args(arguments)?;
+                let title = args.title.trim();
+                if title.is_empty() {
+                    return Err("the task title must not be empty".to_string());
+                }
+                let task_c = TaskForCreateInternal {
+                    title: title.to_string(),
+                    created_by: self.ctx.user_id(),
+                    ..Default::default()
+                };
+                let task = TaskBmc::create(self.ctx, self.mm, task_c).await.map_err(client_error)?;
+                Ok(json!(task))
+            }
+            "calculate" => {
+                let args: CalculateArgs = parse_args(arguments)?;
+                let value = calculate(&args.expression)?;
+                Ok(json!({ "expression": args.expression, "result": value }))
+            }
+            other => Err(format!("unknown tool `{other}`")),
+        }
+    }
+}
+
+fn parse_args<T: for<'de> Deserialize<'de>>(arguments: &str) -> core::result::Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("invalid arguments: {e}"))
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Calculator                                                               */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+/// Longest expression the calculator accepts.
+const MAX_EXPRESSION_CHARS: usize = 256;
+/// Parentheses, unary minus and exponents nested deeper than this are
+/// rejected; the expression comes from the model and the parser recurses.
+const MAX_NESTING: usize = 32;
+
+/// Evaluates `+ - * / % ^`, parentheses and unary minus over decimal
+/// numbers. `^` binds tightest and is right-associative.
+pub fn calculate(expression: &str) -> core::result::Result<f64, String> {
+    if expression.chars().count() > MAX_EXPRESSION_CHARS {
+        return Err(format!("expressions are limited to {MAX_EXPRESSION_CHARS} characters"));
+    }
+    let mut parser = Calculator {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.sum()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("unexpected `{c}` at position {}", parser.pos + 1));
+    }
+    match value.is_finite() {
+        true => Ok(value),
+        false => Err("the result is not a finite number".to_string()),
+    }
+}
+
+struct Calculator {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Calculator {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> core::result::Result<f64, String>,
+    ) -> core::result::Result<f64, String> {
+        if self.depth >= MAX_NESTING {
+            return Err("expression nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn sum(&mut self) -> core::result::Result<f64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> core::result::Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> core::result::Result<f64, String> {
+        match self.eat('-') {
+            true => Ok(-self.nested(Self::unary)?),
+            false => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> core::result::Result<f64, String> {
+        let base = self.atom()?;
+        match self.eat('^') {
+            true => Ok(base.powf(self.nested(Self::unary)?)),
+            false => Ok(base),
+        }
+    }
+
+    fn atom(&mut self) -> core::result::Result<f64, String> {
+        if self.eat('(') {
+            let value = self.nested(Self::sum)?;
+            if !self.eat(')') {
+                return Err("missing `)`".to_string());
+            }
+            return Ok(value);
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(match self.peek() {
+                Some(c) => format!("unexpected `{c}` at position {}", self.pos + 1),
+                None => "unexpected end of expression".to_string(),
+            });
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        number.parse().map_err(|_| format!("invalid number `{number}`"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate;
+
+    #[test]
+    fn calculate_follows_precedence() {
+        assert_eq!(calculate("2 + 3 * 4"), Ok(14.0));
+        assert_eq!(calculate("(2 + 3) * 4"), Ok(20.0));
+        assert_eq!(calculate("17 % 5 + 1"), Ok(3.0));
+        assert_eq!(calculate("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(calculate("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(calculate("10 / 4"), Ok(2.5));
+    }
+
+    #[test]
+    fn calculate_rejects_bad_expressions() {
+        assert!(calculate("1 / 0").is_err());
+        assert!(calculate("1 % 0").is_err());
+        assert!(calculate("(1 + 2").is_err());
+        assert!(calculate("2 +").is_err());
+        assert!(calculate("2 x 3").is_err());
+    }
+
+    #[test]
+    fn calculate_limits_nesting_and_length() {
+        let nested = format!("{}1{}", "(".repeat(40), ")".repeat(40));
+        assert_eq!(calculate(&nested), Err("expression nested too deeply".to_string()));
+        assert_eq!(calculate(&format!("{}1", "-".repeat(40))), Err("expression nested too deeply".to_string()));
+        assert!(calculate(&format!("{}1{}", "(".repeat(10), ")".repeat(10))).is_ok());
+        assert!(calculate(&"1+".repeat(200)).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Answer cache in Redis, keyed by the normalized question, the pipeline
+//! config and the corpus version. Ingesting or deleting a document bumps the
+//! corpus version, which orphans every cached answer; the TTL cleans them up.
+//! Editing a prompt template changes the key as well.
+
+use std::fmt;
+
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::model::manager::AnswerWithSources;
+use crate::model::pipeline::PipelineConfig;
+use crate::model::prompt::PromptSet;
+use crate::{Ctx, Error, Result};
+
+const CORPUS_VERSION_KEY: &str = "kb:corpus_version";
+/// Questions kept per similarity index; later answers are only found exactly.
+const MAX_SIMILARITY_ENTRIES: usize = 1000;
+
+#[derive(Clone)]
+pub struct AnswerCache {
+    conn: ConnectionManager,
+    ttl_secs: u64,
+    similarity: Option<f32>,
+    per_user: bool,
+}
+
+impl fmt::Debug for AnswerCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnswerCache")
+            .field("ttl_secs", &self.ttl_secs)
+            .field("similarity", &self.similarity)
+            .field("per_user", &self.per_user)
+            .finish()
+    }
+}
+
+/// Where one question's answer is cached.
+#[derive(Debug)]
+pub struct CacheKey {
+    /// Scope, corpus version and config hash, shared by all questions that
+    /// may reuse each other's answers.
+    prefix: String,
+    question_hash: String,
+    /// Filled by the similarity lookup and reused when storing the answer.
+    embedding: Option<Vec<f32>>,
+}
+
+impl CacheKey {
+    fn answer(&self, question_hash: &str) -> String {
+        format!("{}:{question_hash}", self.prefix)
+    }
+
+    /// Redis hash of question hash to embedding, for near-duplicate lookups.
+    fn index(&self) -> String {
+        format!("{}:index", self.prefix)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    embedding: Vec<f32>,
+}
+
+/// `exact` or `similar`, as reported in the hit metrics.
+pub type HitKind = &'static str;
+
+impl AnswerCache {
+    /// `None` when `SERVICE_ANSWER_CACHE_TTL_SECS` is 0, or when Redis cannot
+    /// be reached at startup: the service then runs without the cache.
+    pub async fn connect(config: &Config) -> Option<Self> {
+        if config.ANSWER_CACHE_TTL_SECS == 0 {
+            return None;
+        }
+        let conn = match redis::Client::open(config.REDIS_URL.as_str()) {
+            Ok(client) => client.get_connection_manager().await,
+            Err(e) => Err(e),
+        };
+        let conn = match conn {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("answer cache: cannot connect to Redis, running without the cache: {e}");
+                return None;
+            }
+        };
+
+        Some(Self {
+            conn,
+            ttl_secs: config.ANSWER_CACHE_TTL_SECS,
+            similarity: config.ANSWER_CACHE_SIMILARITY,
+            per_user: config.ANSWER_CACHE_PER_USER,
+        })
+    }
+
+    /// Invalidates every cached answer. Best effort: a failure only leaves
+    /// stale answers around until their TTL runs out.
+    pub async fn bump_corpus_version(&self) {
+        let result: redis::RedisResult<u64> = self.conn.clone().incr(CORPUS_VERSION_KEY, 1).await;
+        if let Err(e) = result {
+            warn!("answer cache: failed to bump corpus version: {e}");
+        }
+    }
+
+    pub async fn key(
+        &self,
+        ctx: &Ctx,
+        pipeline: &str,
+        model: &str,
+        prompt: &str,
+        config: &PipelineConfig,
+        prompts: &PromptSet,
+    ) -> Option<CacheKey> {
+        let version: Option<u64> = match self.conn.clone().get(CORPUS_VERSION_KEY).await {
+            Ok(version) => version,
+            Err(e) => {
+                warn!("answer cache: failed to read corpus version: {e}");
+                return None;
+            }
+        };
+        let scope = match self.per_user {
+            true => format!("u{}", ctx.user_id()),
+            false => "all".to_string(),
+        };
+        let config = serde_json::to_string(config).ok()?;
+        let config_hash = hash(&[pipeline, model, &config, &prompts.fingerprint()]);
+
+        Some(CacheKey {
+            prefix: format!("kb:answer:{scope}:{}:{config_hash}", version.unwrap_or_default()),
+            question_hash: hash(&[&normalize(prompt)]),
+            embedding: None,
+        })
+    }
+
+    async fn get(&self, answer_key: &str) -> Option<AnswerWithSources> {
+        let cached: Option<String> = match self.conn.clone().get(answer_key).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                warn!("answer cache: lookup failed: {e}");
+                return None;
+            }
+        };
+        serde_json::from_str(&cached?).ok()
+    }
+
+    /// Exact lookup, then the most similar earlier question above the threshold.
+    pub async fn lookup(
+        &self,
+        key: &mut CacheKey,
+        embed: impl std::future::Future<Output = Option<Vec<f32>>>,
+    ) -> Option<(AnswerWithSources, HitKind)> {
+        if let Some(answer) = self.get(&key.answer(&key.question_hash)).await {
+            return Some((answer, "exact"));
+        }
+        let threshold = self.similarity?;
+
+        let embedding = embed.await?;
+        let entries: Vec<(String, String)> = match self.conn.clone().hgetall(key.index()).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("answer cache: similarity lookup failed: {e}");
+                Vec::new()
+            }
+        };
+        let best = entries
+            .into_iter()
+            .filter_map(|(field, entry)| {
+                let entry: IndexEntry = serde_json::from_str(&entry).ok()?;
+                Some((field, cosine(&embedding, &entry.embedding)))
+            })
+            .filter(|(_, score)| *score >= threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        key.embedding = Some(embedding);
+
+        let (question_hash, _) = best?;
+        self.get(&key.answer(&question_hash)).await.map(|answer| (answer, "similar"))
+    }
+
+    pub async fn put(&self, key: &CacheKey, answer: &AnswerWithSources) {
+        let Ok(value) = serde_json::to_string(answer) else {
+            return;
+        };
+        let mut conn = self.conn.clone();
+        if let Err(e) = conn.set_ex::<_, _, ()>(key.answer(&key.question_hash), value, self.ttl_secs).await {
+            warn!("answer cache: store failed: {e}");
+            return;
+        }
+
+        let Some(embedding) = &key.embedding else {
+            return;
+        };
+        let index = key.index();
+        let size: usize = conn.hlen(&index).await.unwrap_or(MAX_SIMILARITY_ENTRIES);
+        if size >= MAX_SIMILARITY_ENTRIES {
+            return;
+        }
+        let entry = serde_json::to_string(&IndexEntry { embedding: embedding.clone() }).unwrap_or_default();
+        let result: redis::RedisResult<()> = redis::pipe()
+            .hset(&index, &key.question_hash, entry)
+            .ignore()
+            .expire(&index, self.ttl_secs as i64)
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            warn!("answer cache: similarity index update failed: {e}");
+        }
+    }
+}
+
+/// Case, surrounding whitespace, repeated spaces and trailing punctuation do
+/// not change the question.
+fn normalize(prompt: &str) -> String {
+    prompt
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['?', '!', '.'])
+        .to_lowercase()
+}
+
+fn hash(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    base64_url::encode(&hasher.finalize())
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
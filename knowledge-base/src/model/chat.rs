@@ -1,20 +1,29 @@
 //! src/model/chat.rs
 //! conversation + message data-access helpers
 
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};                    // ← brings `chrono` into scope
 use modql::field::{Fields, HasFields};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgRow, FromRow};
+use sqlx::{types::Json, FromRow};
 
 use crate::{
     model::{
         base,
         base::{CommonIden, DbBmc},
+        chat_events::ChatEvent,
+        manager::AnswerWithSources,
     },
-    Ctx, Result,
+    utils::token,
+    Ctx, Error, Result,
 };
 
 
+/// Title of conversations created without one, replaced by a generated
+/// title after the first exchange.
+pub const DEFAULT_TITLE: &str = "Untitled";
+
 /* ────────────────────────────────────────────────────────────────────────── */
 /*  Data structures                                                          */
 /* ────────────────────────────────────────────────────────────────────────── */
@@ -24,7 +33,17 @@ pub struct Conversation {
     pub id: i64,
     pub owner_id: i64,
     pub title: String,
+    pub model: Option<String>,
+    /// Last message of the branch being shown; new prompts continue it.
+    pub active_leaf_id: Option<i64>,
+    pub archived: bool,
+    pub pinned: bool,
+    /// Rolling summary of the messages that no longer fit the chat history.
+    pub summary: Option<String>,
+    /// Last message folded into `summary`.
+    pub summary_until_id: Option<i64>,
     pub created_at: DateTime<Utc>,
+    /// Time of the last message.
     pub updated_at: DateTime<Utc>,
 }
 
@@ -32,26 +51,105 @@ pub struct Conversation {
 pub struct ConversationForInsert {
     pub owner_id: i64,
     pub title: String,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Default, Fields, Deserialize)]
+pub struct ConversationForUpdate {
+    pub title: Option<String>,
+    pub model: Option<String>,
+    pub archived: Option<bool>,
+    pub pinned: Option<bool>,
+}
+
+/// Conversation title or message matching a search, with the matched words
+/// wrapped in `<mark>` tags.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct SearchHit {
+    pub conversation_id: i64,
+    pub title: String,
+    pub archived: bool,
+    /// `None` when the title matched.
+    pub message_id: Option<i64>,
+    pub snippet: String,
+    pub rank: f64,
+    pub matched_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Fields, FromRow, Serialize)]
 pub struct Message {
     pub id: i64,
     pub conversation_id: i64,
+    /// Message this one follows; edits and regenerations share a parent.
+    pub parent_id: Option<i64>,
     pub sender: String,
     pub content: String,
     pub token_count: i32,
+    /// Groundedness of an assistant answer, when checked.
+    pub confidence: Option<f64>,
+    pub grounded: Option<bool>,
+    /// `cancelled` for answers stopped while generating, holding what was produced until then.
+    pub status: String,
     pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Fields)]
 pub struct MessageForInsert {
     pub conversation_id: i64,
+    pub parent_id: Option<i64>,
     pub sender: String,
     pub content: String,
     pub token_count: i32,
+    pub confidence: Option<f64>,
+    pub grounded: Option<bool>,
+    pub status: String,
+}
+
+/// What a user may do with a conversation, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    /// Read messages and rate answers.
+    Read,
+    /// Also send prompts, regenerate, edit and switch branches.
+    Write,
+    /// Also rename, archive, delete and manage collaborators.
+    Own,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollaboratorRole {
+    Viewer,
+    Editor,
+}
+
+impl CollaboratorRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CollaboratorRole::Viewer => "viewer",
+            CollaboratorRole::Editor => "editor",
+        }
+    }
+
+    fn access(role: &str) -> Access {
+        match role {
+            "editor" => Access::Write,
+            _ => Access::Read,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Collaborator {
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    pub added_at: DateTime<Utc>,
+}
+
+const MESSAGE_COLUMNS: &str =
+    "id, conversation_id, parent_id, sender, content, token_count, confidence, grounded, status, created_at";
+
 /* ────────────────────────────────────────────────────────────────────────── */
 /*  BMC wrappers                                                             */
 /* ────────────────────────────────────────────────────────────────────────── */
@@ -72,20 +170,73 @@ impl DbBmc for MessageBmc {
 
 impl ConversationBmc {
 
-    pub async fn get<E>(
+    /// Conversation `id` if the current user may read it. Conversations of
+    /// other users are reported as not found.
+    pub async fn get(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+    ) -> Result<Conversation> {
+        Self::get_with_access(ctx, mm, id, Access::Read).await
+    }
+
+    /// Conversation `id` if the current user owns it.
+    pub async fn get_owned(
         ctx: &Ctx,
         mm: &crate::model::manager::ModelManager,
         id: i64,
-    ) -> Result<E>
-    where
-        E: for<'r> FromRow<'r, PgRow> + Unpin + Send + HasFields,
-    {
-        base::get::<Self, _>(ctx, mm, id).await
+    ) -> Result<Conversation> {
+        Self::get_with_access(ctx, mm, id, Access::Own).await
     }
+
+    /// Conversation `id` if the current user has at least `required` access:
+    /// as its owner, or as a collaborator the owner added.
+    pub async fn get_with_access(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        required: Access,
+    ) -> Result<Conversation> {
+        let conv: Conversation = base::get::<Self, _>(ctx, mm, id).await?;
+        if Self::access(ctx, mm, &conv).await? < Some(required) {
+            return Err(Error::EntityNotFound { entity: Self::TABLE, id });
+        }
+        Ok(conv)
+    }
+
+    async fn access(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        conv: &Conversation,
+    ) -> Result<Option<Access>> {
+        if conv.owner_id == ctx.user_id() {
+            return Ok(Some(Access::Own));
+        }
+        let role: Option<(String,)> = sqlx::query_as(
+            "SELECT role FROM conversation_collaborator WHERE conversation_id = $1 AND user_id = $2"
+        )
+            .bind(conv.id)
+            .bind(ctx.user_id())
+            .fetch_optional(mm.db())
+            .await?;
+        Ok(role.map(|(role,)| CollaboratorRole::access(&role)))
+    }
+
+    /// Checks `required` access without loading the conversation for the caller.
+    pub async fn require(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        required: Access,
+    ) -> Result<()> {
+        Self::get_with_access(ctx, mm, id, required).await.map(|_| ())
+    }
+
     pub async fn create(
         ctx: &Ctx,
         mm: &crate::model::manager::ModelManager,
         title: &str,
+        model: Option<&str>,
     ) -> Result<i64> {
         base::create::<Self, _>(
             ctx,
@@ -93,15 +244,18 @@ impl ConversationBmc {
             ConversationForInsert {
                 owner_id: ctx.user_id(),
                 title: title.to_owned(),
+                model: model.map(str::to_owned),
             },
         )
             .await
     }
 
-    /// Most-recent first, only conversations owned by the current user.
+    /// Pinned first, then most recently active, only conversations owned by
+    /// the current user and either archived or not.
     pub async fn list_for_user(
         ctx: &Ctx,
         mm: &crate::model::manager::ModelManager,
+        archived: bool,
     ) -> Result<Vec<Conversation>> {
         use sea_query::{Alias, Expr, Order, PostgresQueryBuilder, Query};
         use sea_query_binder::SqlxBinder;
@@ -110,6 +264,38 @@ impl ConversationBmc {
         q.from(Self::table_ref())
             .columns(Conversation::field_column_refs())
             .and_where(Expr::col(Alias::new("owner_id")).eq(ctx.user_id()))
+            .and_where(Expr::col(Alias::new("archived")).eq(archived))
+            .order_by(Alias::new("pinned"), Order::Desc)
+            .order_by(Alias::new("updated_at"), Order::Desc)
+            .order_by(CommonIden::Id, Order::Desc);
+
+        let (sql, values) = q.build_sqlx(PostgresQueryBuilder);
+        let convs = sqlx::query_as_with::<_, Conversation, _>(&sql, values)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(convs)
+    }
+
+    /// Conversations other users shared with the current user, most recently
+    /// active first.
+    pub async fn list_shared_with_user(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+    ) -> Result<Vec<Conversation>> {
+        use sea_query::{Alias, Expr, Order, PostgresQueryBuilder, Query};
+        use sea_query_binder::SqlxBinder;
+
+        let mut shared = Query::select();
+        shared
+            .column(Alias::new("conversation_id"))
+            .from(Alias::new("conversation_collaborator"))
+            .and_where(Expr::col(Alias::new("user_id")).eq(ctx.user_id()));
+
+        let mut q = Query::select();
+        q.from(Self::table_ref())
+            .columns(Conversation::field_column_refs())
+            .and_where(Expr::col(CommonIden::Id).in_subquery(shared))
+            .order_by(Alias::new("updated_at"), Order::Desc)
             .order_by(CommonIden::Id, Order::Desc);
 
         let (sql, values) = q.build_sqlx(PostgresQueryBuilder);
@@ -118,6 +304,211 @@ impl ConversationBmc {
             .await?;
         Ok(convs)
     }
+
+    pub async fn update(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        conv_u: ConversationForUpdate,
+    ) -> Result<Conversation> {
+        let ConversationForUpdate { title, model, archived, pinned } = &conv_u;
+        if title.is_none() && model.is_none() && archived.is_none() && pinned.is_none() {
+            return Err(Error::ConversationInvalid("nothing to update".to_string()));
+        }
+        if conv_u.title.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return Err(Error::ConversationInvalid("title must not be empty".to_string()));
+        }
+        Self::get_owned(ctx, mm, id).await?;
+        base::update::<Self, _>(ctx, mm, id, conv_u).await?;
+        Self::get(ctx, mm, id).await
+    }
+
+    /// Deletes the conversation with its messages and their feedback.
+    pub async fn delete(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+    ) -> Result<Conversation> {
+        let conv = Self::get_owned(ctx, mm, id).await?;
+        base::delete::<Self>(ctx, mm, id).await?;
+        mm.chat_hub.publish(id, ChatEvent::AccessChanged);
+        Ok(conv)
+    }
+
+    pub async fn list_collaborators(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+    ) -> Result<Vec<Collaborator>> {
+        Self::require(ctx, mm, id, Access::Read).await?;
+        let collaborators = sqlx::query_as::<_, Collaborator>(
+            "SELECT cc.user_id, u.username, cc.role, cc.added_at \
+             FROM conversation_collaborator cc JOIN \"user\" u ON u.id = cc.user_id \
+             WHERE cc.conversation_id = $1 ORDER BY cc.added_at"
+        )
+            .bind(id)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(collaborators)
+    }
+
+    /// Grants `user_id` access to the conversation, or changes their role.
+    pub async fn set_collaborator(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        user_id: i64,
+        role: CollaboratorRole,
+    ) -> Result<()> {
+        let conv = Self::get_owned(ctx, mm, id).await?;
+        if user_id == conv.owner_id {
+            return Err(Error::ConversationInvalid("the owner cannot be a collaborator".to_string()));
+        }
+        sqlx::query(
+            "INSERT INTO conversation_collaborator (conversation_id, user_id, role) VALUES ($1, $2, $3) \
+             ON CONFLICT (conversation_id, user_id) DO UPDATE SET role = EXCLUDED.role"
+        )
+            .bind(id)
+            .bind(user_id)
+            .bind(role.as_str())
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_collaborator(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        user_id: i64,
+    ) -> Result<()> {
+        Self::get_owned(ctx, mm, id).await?;
+        let result = sqlx::query("DELETE FROM conversation_collaborator WHERE conversation_id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(mm.db())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::EntityNotFound { entity: "conversation_collaborator", id: user_id });
+        }
+        mm.chat_hub.publish(id, ChatEvent::AccessChanged);
+        Ok(())
+    }
+
+    // -- bookkeeping on behalf of operations that already checked access
+
+    /// Sets a generated title unless the conversation was named meanwhile.
+    pub async fn set_generated_title(
+        _ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        title: &str,
+    ) -> Result<()> {
+        sqlx::query("UPDATE conversation SET title = $2 WHERE id = $1 AND title = $3")
+            .bind(id)
+            .bind(title)
+            .bind(DEFAULT_TITLE)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_summary(
+        _ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        summary: &str,
+        until_id: i64,
+    ) -> Result<()> {
+        sqlx::query("UPDATE conversation SET summary = $2, summary_until_id = $3 WHERE id = $1")
+            .bind(id)
+            .bind(summary)
+            .bind(until_id)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+
+    /// Marks the conversation as active now, for the list order.
+    pub async fn touch(
+        _ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+    ) -> Result<()> {
+        sqlx::query("UPDATE conversation SET updated_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+
+    /// Full-text search over the current user's conversation titles and
+    /// messages, best matches first. `query` takes web search syntax
+    /// (`"exact phrase"`, `or`, `-excluded`).
+    pub async fn search(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>> {
+        let hits = sqlx::query_as::<_, SearchHit>(
+            "WITH q AS (SELECT websearch_to_tsquery('english', $2) AS query) \
+             SELECT c.id AS conversation_id, c.title, c.archived, NULL::bigint AS message_id, \
+                    ts_headline('english', c.title, q.query, 'StartSel=<mark>, StopSel=</mark>, HighlightAll=true') AS snippet, \
+                    ts_rank(to_tsvector('english', c.title), q.query)::float8 AS rank, c.updated_at AS matched_at \
+             FROM conversation c CROSS JOIN q \
+             WHERE c.owner_id = $1 AND to_tsvector('english', c.title) @@ q.query \
+             UNION ALL \
+             SELECT c.id, c.title, c.archived, m.id, \
+                    ts_headline('english', m.content, q.query, 'StartSel=<mark>, StopSel=</mark>, MinWords=10, MaxWords=30, MaxFragments=2'), \
+                    ts_rank(to_tsvector('english', m.content), q.query)::float8, m.created_at \
+             FROM message m JOIN conversation c ON c.id = m.conversation_id CROSS JOIN q \
+             WHERE c.owner_id = $1 AND to_tsvector('english', m.content) @@ q.query \
+             ORDER BY rank DESC, matched_at DESC LIMIT $3"
+        )
+            .bind(ctx.user_id())
+            .bind(query)
+            .bind(limit)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(hits)
+    }
+
+    /// Shows the branch ending in `leaf_id`.
+    pub async fn set_active_leaf(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        leaf_id: i64,
+    ) -> Result<()> {
+        Self::require(ctx, mm, id, Access::Write).await?;
+        sqlx::query("UPDATE conversation SET active_leaf_id = $2 WHERE id = $1")
+            .bind(id)
+            .bind(leaf_id)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+
+    /// Moves the active branch on to `leaf_id` (a new answer) unless the user
+    /// switched branches since `expected` was active.
+    pub async fn advance_leaf(
+        _ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        id: i64,
+        expected: Option<i64>,
+        leaf_id: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE conversation SET active_leaf_id = $3 WHERE id = $1 AND active_leaf_id IS NOT DISTINCT FROM $2"
+        )
+            .bind(id)
+            .bind(expected)
+            .bind(leaf_id)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
 }
 
 impl MessageBmc {
@@ -125,41 +516,134 @@ impl MessageBmc {
         ctx: &Ctx,
         mm: &crate::model::manager::ModelManager,
         conv_id: i64,
+        parent_id: Option<i64>,
         sender: &str,
         content: &str,
         token_count: i32,
     ) -> Result<i64> {
-        base::create::<Self, _>(
+        ConversationBmc::require(ctx, mm, conv_id, Access::Write).await?;
+        let id = base::create::<Self, _>(
             ctx,
             mm,
             MessageForInsert {
                 conversation_id: conv_id,
+                parent_id,
                 sender: sender.into(),
                 content: content.into(),
                 token_count,
+                confidence: None,
+                grounded: None,
+                status: "complete".into(),
             },
         )
-            .await
+            .await?;
+        ConversationBmc::touch(ctx, mm, conv_id).await?;
+        Ok(id)
     }
 
-    /// newest → oldest, but only while the running token sum ≤ `limit`
-    pub async fn recent(
+    pub async fn add_answer(
         ctx: &Ctx,
         mm: &crate::model::manager::ModelManager,
         conv_id: i64,
-        limit: i32,
-    ) -> Result<Vec<Message>> {
-        use sea_query::{Alias, Expr, Order, PostgresQueryBuilder, Query};
-        use sea_query_binder::SqlxBinder;
+        parent_id: i64,
+        answer: &AnswerWithSources,
+    ) -> Result<i64> {
+        ConversationBmc::require(ctx, mm, conv_id, Access::Write).await?;
+        let id = base::create::<Self, _>(
+            ctx,
+            mm,
+            MessageForInsert {
+                conversation_id: conv_id,
+                parent_id: Some(parent_id),
+                sender: "assistant".into(),
+                content: answer.answer.clone(),
+                token_count: token::count(&answer.answer),
+                confidence: answer.confidence,
+                grounded: answer.grounded,
+                status: "complete".into(),
+            },
+        )
+            .await?;
 
-        let mut q = Query::select();
-        q.from(Self::table_ref())
-            .columns(Message::field_column_refs())
-            .and_where(Expr::col(Alias::new("conversation_id")).eq(conv_id))
-            .order_by(CommonIden::Id, Order::Desc);
+        // what the answer was based on, for reviewing feedback later
+        sqlx::query(
+            "INSERT INTO message_trace (message_id, pipeline, documents, contexts, agent_steps) \
+             VALUES ($1, $2, $3, $4, $5)"
+        )
+            .bind(id)
+            .bind(answer.pipeline.as_ref().map(Json))
+            .bind(Json(&answer.documents))
+            .bind(&answer.contexts)
+            .bind(Json(&answer.agent_steps))
+            .execute(mm.db())
+            .await?;
+        ConversationBmc::touch(ctx, mm, conv_id).await?;
+        Ok(id)
+    }
 
-        let (sql, v) = q.build_sqlx(PostgresQueryBuilder);
-        let mut msgs = sqlx::query_as_with::<_, Message, _>(&sql, v)
+    /// Stores what was generated of an answer before it was cancelled.
+    pub async fn add_cancelled(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        conv_id: i64,
+        parent_id: i64,
+        partial: &str,
+    ) -> Result<i64> {
+        ConversationBmc::require(ctx, mm, conv_id, Access::Write).await?;
+        let id = base::create::<Self, _>(
+            ctx,
+            mm,
+            MessageForInsert {
+                conversation_id: conv_id,
+                parent_id: Some(parent_id),
+                sender: "assistant".into(),
+                content: partial.into(),
+                token_count: token::count(partial),
+                confidence: None,
+                grounded: None,
+                status: "cancelled".into(),
+            },
+        )
+            .await?;
+        ConversationBmc::touch(ctx, mm, conv_id).await?;
+        Ok(id)
+    }
+
+    /// Message `id` of conversation `conv_id`.
+    pub async fn get_in(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        conv_id: i64,
+        id: i64,
+    ) -> Result<Message> {
+        ConversationBmc::require(ctx, mm, conv_id, Access::Read).await?;
+        sqlx::query_as::<_, Message>(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM message WHERE id = $1 AND conversation_id = $2"
+        ))
+            .bind(id)
+            .bind(conv_id)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or(Error::EntityNotFound { entity: "message", id })
+    }
+
+    /// Active branch from its leaf up to the first prompt, newest → oldest,
+    /// but only while the running token sum ≤ `limit`
+    pub async fn active_path(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        conv_id: i64,
+        limit: i32,
+    ) -> Result<Vec<Message>> {
+        ConversationBmc::require(ctx, mm, conv_id, Access::Read).await?;
+        let mut msgs = sqlx::query_as::<_, Message>(&format!(
+            "WITH RECURSIVE path AS ( \
+                 SELECT m.* FROM message m JOIN conversation c ON c.active_leaf_id = m.id WHERE c.id = $1 \
+                 UNION ALL \
+                 SELECT m.* FROM message m JOIN path p ON m.id = p.parent_id \
+             ) SELECT {MESSAGE_COLUMNS} FROM path ORDER BY id DESC"
+        ))
+            .bind(conv_id)
             .fetch_all(mm.db())
             .await?;
 
@@ -171,4 +655,69 @@ impl MessageBmc {
         });
         Ok(msgs)
     }
+
+    /// Messages `id` follows up on, oldest first, without `id` itself.
+    pub async fn ancestors(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        conv_id: i64,
+        id: i64,
+    ) -> Result<Vec<Message>> {
+        ConversationBmc::require(ctx, mm, conv_id, Access::Read).await?;
+        let msgs = sqlx::query_as::<_, Message>(&format!(
+            "WITH RECURSIVE path AS ( \
+                 SELECT m.* FROM message m \
+                 WHERE m.id = (SELECT parent_id FROM message WHERE id = $2 AND conversation_id = $1) \
+                 UNION ALL \
+                 SELECT m.* FROM message m JOIN path p ON m.id = p.parent_id \
+             ) SELECT {MESSAGE_COLUMNS} FROM path ORDER BY id"
+        ))
+            .bind(conv_id)
+            .bind(id)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(msgs)
+    }
+
+    /// `(id, parent_id)` of every message in the conversation, oldest first.
+    pub async fn tree(
+        ctx: &Ctx,
+        mm: &crate::model::manager::ModelManager,
+        conv_id: i64,
+    ) -> Result<MessageTree> {
+        ConversationBmc::require(ctx, mm, conv_id, Access::Read).await?;
+        let edges: Vec<(i64, Option<i64>)> = sqlx::query_as(
+            "SELECT id, parent_id FROM message WHERE conversation_id = $1 ORDER BY id"
+        )
+            .bind(conv_id)
+            .fetch_all(mm.db())
+            .await?;
+
+        let mut children: HashMap<Option<i64>, Vec<i64>> = HashMap::new();
+        for (id, parent_id) in edges {
+            children.entry(parent_id).or_default().push(id);
+        }
+        Ok(MessageTree { children })
+    }
+}
+
+/// Parent → children links of a conversation, for navigating branches.
+#[derive(Debug, Default)]
+pub struct MessageTree {
+    children: HashMap<Option<i64>, Vec<i64>>,
+}
+
+impl MessageTree {
+    /// Alternatives to `message` (itself included), oldest first.
+    pub fn siblings(&self, message: &Message) -> Vec<i64> {
+        self.children.get(&message.parent_id).cloned().unwrap_or_default()
+    }
+
+    /// End of the newest branch below `id`, which is where selecting `id` leads.
+    pub fn newest_leaf(&self, mut id: i64) -> i64 {
+        while let Some(child) = self.children.get(&Some(id)).and_then(|c| c.last()) {
+            id = *child;
+        }
+        id
+    }
 }
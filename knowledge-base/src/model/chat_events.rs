@@ -0,0 +1,159 @@
+//! src/model/chat_events.rs
+//! live events of a conversation (new messages, pipeline progress, answer
+//! tokens) fanned out to every connected client, and the registry of
+//! answers being generated so they can be cancelled
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::{broadcast, oneshot};
+use uuid::Uuid;
+
+use crate::model::chat::Message;
+
+/// Events buffered per conversation before slow clients start lagging.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// A prompt was stored, from this client or another device.
+    Message { message: Message },
+    /// Answering `prompt_id` started; `generation_id` cancels it.
+    Generating { prompt_id: i64, generation_id: Uuid },
+    /// The pipeline answering `prompt_id` entered a stage.
+    Progress { prompt_id: i64, phase: &'static str, stage: &'static str },
+    /// Next piece of the answer being generated.
+    Token { prompt_id: i64, delta: String },
+    /// The answer was stored, or what there was of it when it was cancelled.
+    Answer { prompt_id: i64, message: Message },
+    Failed { prompt_id: i64, error: String },
+    Cancelled { prompt_id: i64, generation_id: Uuid },
+    /// The client fell behind and missed events; it should reload the messages.
+    Lagged { skipped: u64 },
+    /// A client request on the socket was rejected.
+    Error { error: String },
+    /// Collaborators were removed or the conversation was deleted. Sockets
+    /// check their access again and close if it is gone; never sent to clients.
+    #[serde(skip)]
+    AccessChanged,
+}
+
+/// Phase reported to clients for a pipeline stage.
+fn phase(stage: &str) -> &'static str {
+    match stage {
+        "subquestions" | "rewrite" | "hyde" | "multi_query" => "generating_subquestions",
+        "embed" | "sparse_embed" | "retrieve" | "rerank" => "retrieving",
+        "summary" => "summarizing",
+        "answer" => "answering",
+        "search_knowledge_base" | "get_document" | "list_tasks" | "create_task" | "calculate" => "using_tools",
+        _ => "checking",
+    }
+}
+
+/// Asks a generation to stop; it replies with the id of the message
+/// holding the partial answer.
+pub type CancelRequest = oneshot::Sender<i64>;
+
+#[derive(Debug)]
+struct Generation {
+    conv_id: i64,
+    prompt_id: i64,
+    cancel: oneshot::Sender<CancelRequest>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChatHub {
+    channels: Arc<Mutex<HashMap<i64, broadcast::Sender<ChatEvent>>>>,
+    generations: Arc<Mutex<HashMap<Uuid, Generation>>>,
+}
+
+impl ChatHub {
+    pub fn subscribe(&self, conv_id: i64) -> broadcast::Receiver<ChatEvent> {
+        let mut channels = self.channels.lock().unwrap();
+        // drop the channels of conversations nobody listens to anymore
+        channels.retain(|_, tx| tx.receiver_count() > 0);
+        channels
+            .entry(conv_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Sends to the clients of the conversation; a no-op when none is connected.
+    pub fn publish(&self, conv_id: i64, event: ChatEvent) {
+        if let Some(tx) = self.channels.lock().unwrap().get(&conv_id) {
+            let _ = tx.send(event);
+        }
+    }
+
+    pub fn progress(&self, conv_id: i64, prompt_id: i64) -> Progress {
+        Progress { hub: self.clone(), conv_id, prompt_id, partial: Arc::default() }
+    }
+
+    /// Registers an answer about to be generated. The generating task
+    /// watches the returned receiver for a cancel request.
+    pub fn start(&self, conv_id: i64, prompt_id: i64) -> (Uuid, oneshot::Receiver<CancelRequest>) {
+        let generation_id = Uuid::new_v4();
+        let (cancel, cancelled) = oneshot::channel();
+        self.generations
+            .lock()
+            .unwrap()
+            .insert(generation_id, Generation { conv_id, prompt_id, cancel });
+        self.publish(conv_id, ChatEvent::Generating { prompt_id, generation_id });
+        (generation_id, cancelled)
+    }
+
+    /// The generation is done, or can no longer be cancelled. `false` when a
+    /// cancel request got to it first.
+    pub fn finish(&self, generation_id: Uuid) -> bool {
+        self.generations.lock().unwrap().remove(&generation_id).is_some()
+    }
+
+    /// Conversation of a generation still running.
+    pub fn conversation_of(&self, generation_id: Uuid) -> Option<i64> {
+        self.generations.lock().unwrap().get(&generation_id).map(|g| g.conv_id)
+    }
+
+    /// Stops a generation. The receiver yields the id of the message the
+    /// partial answer was stored in. `None` when it is no longer running.
+    pub fn cancel(&self, generation_id: Uuid) -> Option<oneshot::Receiver<i64>> {
+        let generation = self.generations.lock().unwrap().remove(&generation_id)?;
+        let (stored, receiver) = oneshot::channel();
+        generation.cancel.send(stored).ok()?;
+        self.publish(generation.conv_id, ChatEvent::Cancelled { prompt_id: generation.prompt_id, generation_id });
+        Some(receiver)
+    }
+}
+
+/// Reports the progress of one answer to the clients of its conversation.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    hub: ChatHub,
+    conv_id: i64,
+    prompt_id: i64,
+    /// Answer tokens so far, kept if the answer is cancelled.
+    partial: Arc<Mutex<String>>,
+}
+
+impl Progress {
+    pub fn stage(&self, stage: &'static str) {
+        self.hub.publish(self.conv_id, ChatEvent::Progress {
+            prompt_id: self.prompt_id,
+            phase: phase(stage),
+            stage,
+        });
+    }
+
+    pub fn token(&self, delta: &str) {
+        self.partial.lock().unwrap().push_str(delta);
+        self.hub.publish(self.conv_id, ChatEvent::Token {
+            prompt_id: self.prompt_id,
+            delta: delta.to_string(),
+        });
+    }
+
+    pub fn partial(&self) -> String {
+        self.partial.lock().unwrap().clone()
+    }
+}
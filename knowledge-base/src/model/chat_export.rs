@@ -0,0 +1,395 @@
+//! src/model/chat_export.rs
+//! conversations as Markdown, HTML or JSON files, and re-import of the JSON
+//! format (which keeps all branches) for moving chats between deployments
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Write};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::model::chat::{Conversation, ConversationBmc};
+use crate::model::documents::DocumentBmc;
+use crate::model::manager::ModelManager;
+use crate::model::retrieval::RetrievedDocument;
+use crate::utils::token;
+use crate::{Ctx, Error, Result};
+
+/// Version of the JSON export format; imports of other versions are rejected.
+pub const EXPORT_VERSION: u32 = 1;
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Data structures                                                          */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[serde(alias = "markdown")]
+    Md,
+    #[default]
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Md => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Md => "text/markdown; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+/// Whole conversation tree. Message ids are only meaningful within the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationExport {
+    pub version: u32,
+    pub title: String,
+    pub model: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub summary: Option<String>,
+    pub summary_until_id: Option<i64>,
+    /// Last message of the branch that was shown.
+    pub active_leaf_id: Option<i64>,
+    /// Parents come before their children.
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub sender: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    #[serde(default)]
+    pub grounded: Option<bool>,
+    /// `cancelled` for answers stopped while generating.
+    #[serde(default = "complete")]
+    pub status: String,
+    /// Documents an assistant answer was based on.
+    #[serde(default)]
+    pub sources: Vec<RetrievedDocument>,
+}
+
+#[derive(Debug, FromRow)]
+struct MessageRow {
+    id: i64,
+    parent_id: Option<i64>,
+    sender: String,
+    content: String,
+    created_at: DateTime<Utc>,
+    confidence: Option<f64>,
+    grounded: Option<bool>,
+    status: String,
+    documents: Option<Json<Vec<RetrievedDocument>>>,
+}
+
+fn complete() -> String {
+    "complete".to_string()
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  BMC                                                                      */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub struct ChatExportBmc;
+
+impl ChatExportBmc {
+    pub async fn export(ctx: &Ctx, mm: &ModelManager, conv_id: i64) -> Result<ConversationExport> {
+        let conv = ConversationBmc::get(ctx, mm, conv_id).await?;
+        Self::export_conversation(mm, conv).await
+    }
+
+    async fn export_conversation(mm: &ModelManager, conv: Conversation) -> Result<ConversationExport> {
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT m.id, m.parent_id, m.sender, m.content, m.created_at, m.confidence, m.grounded, m.status, t.documents \
+             FROM message m LEFT JOIN message_trace t ON t.message_id = m.id \
+             WHERE m.conversation_id = $1 ORDER BY m.id"
+        )
+            .bind(conv.id)
+            .fetch_all(mm.db())
+            .await?;
+
+        let messages = rows
+            .into_iter()
+            .map(|r| ExportedMessage {
+                id: r.id,
+                parent_id: r.parent_id,
+                sender: r.sender,
+                content: r.content,
+                created_at: r.created_at,
+                confidence: r.confidence,
+                grounded: r.grounded,
+                status: r.status,
+                sources: r.documents.map(|Json(d)| d).unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(ConversationExport {
+            version: EXPORT_VERSION,
+            title: conv.title,
+            model: conv.model,
+            created_at: conv.created_at,
+            updated_at: conv.updated_at,
+            summary: conv.summary,
+            summary_until_id: conv.summary_until_id,
+            active_leaf_id: conv.active_leaf_id,
+            messages,
+        })
+    }
+
+    /// Every conversation the user owns, archived ones included, one file
+    /// each in a zip archive.
+    pub async fn export_all(ctx: &Ctx, mm: &ModelManager, format: ExportFormat) -> Result<Vec<u8>> {
+        let mut convs = ConversationBmc::list_for_user(ctx, mm, false).await?;
+        convs.extend(ConversationBmc::list_for_user(ctx, mm, true).await?);
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for conv in convs {
+            let name = format!("{}-{}.{}", conv.id, slug(&conv.title), format.extension());
+            let export = Self::export_conversation(mm, conv).await?;
+            let content = render(&export, format)?;
+            zip.start_file(name, SimpleFileOptions::default())
+                .map_err(|e| Error::ServiceError(format!("zip export failed: {e}")))?;
+            zip.write_all(content.as_bytes())
+                .map_err(|e| Error::ServiceError(format!("zip export failed: {e}")))?;
+        }
+        let archive = zip
+            .finish()
+            .map_err(|e| Error::ServiceError(format!("zip export failed: {e}")))?;
+        Ok(archive.into_inner())
+    }
+
+    /// Recreates an exported conversation, all branches included, owned by
+    /// the current user. Returns the new conversation id. Sources are kept
+    /// only for documents that exist and can be cited, so a file cannot
+    /// invent citations.
+    pub async fn import(ctx: &Ctx, mm: &ModelManager, export: ConversationExport) -> Result<i64> {
+        if export.version != EXPORT_VERSION {
+            return Err(Error::ChatImportInvalid(format!("unsupported export version {}", export.version)));
+        }
+        if export.title.trim().is_empty() {
+            return Err(Error::ChatImportInvalid("title must not be empty".to_string()));
+        }
+
+        let cited: Vec<String> = export
+            .messages
+            .iter()
+            .flat_map(|m| m.sources.iter().filter_map(|d| d.doc_id.clone()))
+            .collect();
+        let citable: HashSet<String> = DocumentBmc::citable_ids(mm, &cited).await?.into_iter().collect();
+
+        let mut tx = mm.db().begin().await?;
+        let (conv_id,): (i64,) = sqlx::query_as(
+            "INSERT INTO conversation (owner_id, title, model, created_at, updated_at) VALUES ($1, $2, $3, $4, $5) RETURNING id"
+        )
+            .bind(ctx.user_id())
+            .bind(&export.title)
+            .bind(&export.model)
+            .bind(export.created_at)
+            .bind(export.updated_at)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // file ids → new ids
+        let mut ids: HashMap<i64, i64> = HashMap::new();
+        for msg in &export.messages {
+            if !matches!(msg.sender.as_str(), "user" | "assistant") {
+                return Err(Error::ChatImportInvalid(format!("message {}: unknown sender {:?}", msg.id, msg.sender)));
+            }
+            if !matches!(msg.status.as_str(), "complete" | "cancelled") {
+                return Err(Error::ChatImportInvalid(format!("message {}: unknown status {:?}", msg.id, msg.status)));
+            }
+            let parent_id = match msg.parent_id {
+                Some(parent) => Some(*ids.get(&parent).ok_or_else(|| {
+                    Error::ChatImportInvalid(format!("message {}: parent {parent} must come first", msg.id))
+                })?),
+                None => None,
+            };
+            let (id,): (i64,) = sqlx::query_as(
+                "INSERT INTO message (conversation_id, parent_id, sender, content, token_count, confidence, grounded, status, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id"
+            )
+                .bind(conv_id)
+                .bind(parent_id)
+                .bind(&msg.sender)
+                .bind(&msg.content)
+                .bind(token::count(&msg.content))
+                .bind(msg.confidence)
+                .bind(msg.grounded)
+                .bind(&msg.status)
+                .bind(msg.created_at)
+                .fetch_one(&mut *tx)
+                .await?;
+            if ids.insert(msg.id, id).is_some() {
+                return Err(Error::ChatImportInvalid(format!("message id {} is used twice", msg.id)));
+            }
+            let sources: Vec<&RetrievedDocument> = msg
+                .sources
+                .iter()
+                .filter(|d| d.doc_id.as_ref().is_some_and(|id| citable.contains(id)))
+                .collect();
+            if !sources.is_empty() {
+                sqlx::query("INSERT INTO message_trace (message_id, documents) VALUES ($1, $2)")
+                    .bind(id)
+                    .bind(Json(&sources))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        let mapped = |id: Option<i64>| id.and_then(|id| ids.get(&id).copied());
+        let summary_until_id = mapped(export.summary_until_id);
+        sqlx::query(
+            "UPDATE conversation SET active_leaf_id = $2, summary = $3, summary_until_id = $4 WHERE id = $1"
+        )
+            .bind(conv_id)
+            .bind(mapped(export.active_leaf_id).or_else(|| ids.values().max().copied()))
+            .bind(summary_until_id.and(export.summary))
+            .bind(summary_until_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(conv_id)
+    }
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Rendering                                                                */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub fn render(export: &ConversationExport, format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(export)
+            .map_err(|e| Error::ServiceError(format!("json export failed: {e}"))),
+        ExportFormat::Md => Ok(to_markdown(export)),
+        ExportFormat::Html => Ok(to_html(export)),
+    }
+}
+
+/// Messages of the branch that was shown, oldest first.
+pub fn active_branch(export: &ConversationExport) -> Vec<&ExportedMessage> {
+    let by_id: HashMap<i64, &ExportedMessage> = export.messages.iter().map(|m| (m.id, m)).collect();
+    let mut branch = Vec::new();
+    let mut next = export.active_leaf_id.and_then(|id| by_id.get(&id).copied());
+    while let Some(msg) = next {
+        branch.push(msg);
+        next = msg.parent_id.and_then(|id| by_id.get(&id).copied());
+    }
+    branch.reverse();
+    branch
+}
+
+fn role(msg: &ExportedMessage) -> &'static str {
+    match (msg.sender.as_str(), msg.status.as_str()) {
+        ("user", _) => "User",
+        (_, "cancelled") => "Assistant (cancelled)",
+        _ => "Assistant",
+    }
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn source_label(doc: &RetrievedDocument) -> String {
+    let name = doc.doc_name.as_deref().or(doc.doc_id.as_deref()).unwrap_or("unknown document");
+    match doc.title.is_empty() {
+        true => name.to_string(),
+        false => format!("{name}: {}", doc.title),
+    }
+}
+
+fn to_markdown(export: &ConversationExport) -> String {
+    let mut out = format!("# {}\n\n_Exported conversation, started {}_\n", export.title, timestamp(&export.created_at));
+    for msg in active_branch(export) {
+        out.push_str(&format!("\n## {} · {}\n\n{}\n", role(msg), timestamp(&msg.created_at), msg.content));
+        if !msg.sources.is_empty() {
+            out.push_str("\n**Sources**\n\n");
+            for (i, doc) in msg.sources.iter().enumerate() {
+                out.push_str(&format!("{}. {}\n", i + 1, source_label(doc)));
+            }
+        }
+    }
+    out
+}
+
+fn to_html(export: &ConversationExport) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>body{{font-family:sans-serif;max-width:48rem;margin:2rem auto}}\
+         .msg{{margin:1.5rem 0}}.meta{{color:#666;font-size:.85rem}}\
+         .content{{white-space:pre-wrap}}.sources{{font-size:.9rem}}</style></head>\n\
+         <body><h1>{title}</h1><p class=\"meta\">Exported conversation, started {started}</p>\n",
+        title = escape_html(&export.title),
+        started = timestamp(&export.created_at),
+    );
+    for msg in active_branch(export) {
+        out.push_str(&format!(
+            "<div class=\"msg {sender}\"><div class=\"meta\"><strong>{role}</strong> · {at}</div>\
+             <div class=\"content\">{content}</div>",
+            sender = escape_html(&msg.sender),
+            role = role(msg),
+            at = timestamp(&msg.created_at),
+            content = escape_html(&msg.content),
+        ));
+        if !msg.sources.is_empty() {
+            out.push_str("<ol class=\"sources\">");
+            for doc in &msg.sources {
+                out.push_str(&format!("<li>{}</li>", escape_html(&source_label(doc))));
+            }
+            out.push_str("</ol>");
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// File name part derived from a title, e.g. `vpn-setup-on-linux`.
+pub fn slug(title: &str) -> String {
+    let slug = title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-");
+    match slug.is_empty() {
+        true => "conversation".to_string(),
+        false => slug.chars().take(60).collect(),
+    }
+}
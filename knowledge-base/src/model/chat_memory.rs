@@ -0,0 +1,171 @@
+//! src/model/chat_memory.rs
+//! what a chat answer knows of its conversation: a rolling summary of the
+//! messages that no longer fit the history budget plus the recent ones
+//! verbatim, and the title generated from the first exchange
+
+use std::time::Instant;
+
+use swiftide::prompt::Prompt;
+use swiftide::traits::SimplePrompt;
+use tracing::warn;
+
+use crate::config::config;
+use crate::metrics;
+use crate::model::chat::{Conversation, ConversationBmc, Message, MessageBmc};
+use crate::model::llm::LlmClient;
+use crate::model::manager::ModelManager;
+use crate::model::prompt::PromptSet;
+use crate::{Ctx, Error, Result};
+
+const SUMMARY_PROMPT: &str = "You keep a running summary of a conversation between a user and an \
+assistant that answers from an internal knowledge base. Update the summary with the new messages. \
+Keep every fact, name, number, decision and open question that may matter later, drop pleasantries. \
+Reply with the updated summary only.\n\nSummary so far:\n{{ summary }}\n\nNew messages:\n{{ messages }}";
+
+const TITLE_PROMPT: &str = "Write a short title, at most six words, for a conversation that starts \
+like this. Reply with the title only, without quotes.\n\nUser: {{ question }}\nAssistant: {{ answer }}";
+
+/// Pipeline label of the summary and title stages in the metrics.
+const PIPELINE: &str = "chat";
+
+/// Longest generated title kept, in characters.
+const MAX_TITLE_CHARS: usize = 80;
+
+/// History to answer `prompt_id` with: the summary of older messages plus the
+/// recent ones within `SERVICE_CHAT_HISTORY_TOKENS`. Messages that dropped
+/// out of the budget since the last prompt are folded into the summary
+/// first. `None` for the first prompt of a conversation.
+pub async fn history(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    conv: &Conversation,
+    prompt_id: i64,
+    llm: &LlmClient,
+    prompts: &PromptSet,
+) -> Result<Option<String>> {
+    let earlier = MessageBmc::ancestors(ctx, mm, conv.id, prompt_id).await?;
+    if earlier.is_empty() {
+        return Ok(None);
+    }
+
+    // the newest messages that fit the budget stay verbatim
+    let budget = config().CHAT_HISTORY_TOKENS;
+    let mut sum = 0;
+    let keep_from = earlier
+        .iter()
+        .rposition(|m| {
+            sum += m.token_count;
+            sum > budget
+        })
+        .map_or(0, |i| i + 1);
+    let (trimmed, recent) = earlier.split_at(keep_from);
+
+    let summary = match trimmed.last() {
+        None => None,
+        Some(last) => {
+            // the stored summary only applies if it covers the start of this branch
+            let covered = conv
+                .summary_until_id
+                .and_then(|until| trimmed.iter().position(|m| m.id == until))
+                .zip(conv.summary.as_deref());
+            let (previous, pending) = match covered {
+                Some((i, summary)) => (Some(summary), &trimmed[i + 1..]),
+                None => (None, trimmed),
+            };
+            match (previous, pending.is_empty()) {
+                (Some(summary), true) => Some(summary.to_string()),
+                _ => {
+                    let template = prompts.get("chat_summary");
+                    match summarize(llm, template, previous, pending).await {
+                        Ok(summary) => {
+                            ConversationBmc::set_summary(ctx, mm, conv.id, &summary, last.id).await?;
+                            Some(summary)
+                        }
+                        Err(e) => {
+                            warn!("conversation {}: summary update failed: {e}", conv.id);
+                            previous.map(str::to_string)
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    let mut history = String::new();
+    if let Some(summary) = summary {
+        history.push_str(&format!("Summary of earlier messages:\n{summary}\n\n"));
+    }
+    history.push_str(&transcript(recent));
+    Ok(Some(history))
+}
+
+/// Titles a conversation after its first exchange, unless it was named meanwhile.
+/// Best effort: the conversation keeps its default title on failure.
+pub async fn generate_title(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    conv_id: i64,
+    llm: &LlmClient,
+    prompts: &PromptSet,
+    question: &str,
+    answer: &str,
+) {
+    let prompt = Prompt::from(prompts.get("chat_title").unwrap_or(TITLE_PROMPT).to_string())
+        .with_context_value("question", question)
+        .with_context_value("answer", answer);
+    let start = Instant::now();
+    let reply = llm.prompt(prompt).await;
+    metrics::record_stage(PIPELINE, llm.prompt_model(), "chat_title", start.elapsed(), reply.is_ok());
+
+    let title = match reply {
+        Ok(reply) => clean_title(&reply),
+        Err(e) => {
+            warn!("conversation {conv_id}: title generation failed: {e}");
+            return;
+        }
+    };
+    if title.is_empty() {
+        return;
+    }
+    if let Err(e) = ConversationBmc::set_generated_title(ctx, mm, conv_id, &title).await {
+        warn!("conversation {conv_id}: storing the generated title failed: {e}");
+    }
+}
+
+async fn summarize(
+    llm: &LlmClient,
+    template: Option<&str>,
+    summary: Option<&str>,
+    messages: &[Message],
+) -> Result<String> {
+    let prompt = Prompt::from(template.unwrap_or(SUMMARY_PROMPT).to_string())
+        .with_context_value("summary", summary.unwrap_or("(none yet)"))
+        .with_context_value("messages", transcript(messages));
+    let start = Instant::now();
+    let reply = llm.prompt(prompt).await;
+    metrics::record_stage(PIPELINE, llm.prompt_model(), "chat_summary", start.elapsed(), reply.is_ok());
+
+    Ok(reply.map_err(|e| Error::LlmError(e.to_string()))?.trim().to_string())
+}
+
+fn transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let role = if m.sender == "user" { "User" } else { "Assistant" };
+            format!("{role}: {}\n", m.content)
+        })
+        .collect()
+}
+
+/// First line of the reply, without quotes or a trailing period.
+fn clean_title(reply: &str) -> String {
+    let line = reply.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default();
+    let title = line
+        .trim_start_matches("Title:")
+        .trim()
+        .trim_matches(['"', '\'', '*'])
+        .trim_end_matches('.')
+        .trim();
+    title.chars().take(MAX_TITLE_CHARS).collect()
+}
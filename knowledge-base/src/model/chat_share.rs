@@ -0,0 +1,144 @@
+//! src/model/chat_share.rs
+//! public read-only links to a snapshot of a conversation's active branch,
+//! served without login under an unguessable token
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+
+use crate::config::config;
+use crate::model::chat::ConversationBmc;
+use crate::model::chat_export::{self, ChatExportBmc, ConversationExport};
+use crate::model::documents::DocumentBmc;
+use crate::model::manager::ModelManager;
+use crate::{Ctx, Error, Result};
+
+/// Longest lifetime a share link can be given.
+const MAX_TTL_HOURS: i64 = 24 * 365;
+
+const SHARE_COLUMNS: &str = "id, conversation_id, token, expires_at, revoked_at, created_at";
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Data structures                                                          */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ShareForCreate {
+    /// `SERVICE_CHAT_SHARE_TTL_HOURS` when absent.
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Share {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  BMC                                                                      */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub struct ShareBmc;
+
+impl ShareBmc {
+    /// Publishes the conversation as it is now; later messages are not part
+    /// of the link. Owner only.
+    pub async fn create(ctx: &Ctx, mm: &ModelManager, conv_id: i64, share_c: ShareForCreate) -> Result<Share> {
+        ConversationBmc::get_owned(ctx, mm, conv_id).await?;
+        let hours = share_c.expires_in_hours.unwrap_or(config().CHAT_SHARE_TTL_HOURS);
+        if !(1..=MAX_TTL_HOURS).contains(&hours) {
+            return Err(Error::ConversationInvalid(format!(
+                "share links expire after 1 to {MAX_TTL_HOURS} hours"
+            )));
+        }
+
+        let mut snapshot = ChatExportBmc::export(ctx, mm, conv_id).await?;
+        // only the branch being shown is published, and the summary may
+        // mention messages of other branches
+        let branch: HashSet<i64> = chat_export::active_branch(&snapshot).iter().map(|m| m.id).collect();
+        snapshot.messages.retain(|m| branch.contains(&m.id));
+        snapshot.summary = None;
+        snapshot.summary_until_id = None;
+
+        let share = sqlx::query_as::<_, Share>(&format!(
+            "INSERT INTO conversation_share (conversation_id, token, created_by, snapshot, expires_at) \
+             VALUES ($1, $2, $3, $4, $5) RETURNING {SHARE_COLUMNS}"
+        ))
+            .bind(conv_id)
+            .bind(new_token())
+            .bind(ctx.user_id())
+            .bind(Json(&snapshot))
+            .bind(Utc::now() + Duration::hours(hours))
+            .fetch_one(mm.db())
+            .await?;
+        Ok(share)
+    }
+
+    /// Links of a conversation, revoked and expired ones included. Owner only.
+    pub async fn list(ctx: &Ctx, mm: &ModelManager, conv_id: i64) -> Result<Vec<Share>> {
+        ConversationBmc::get_owned(ctx, mm, conv_id).await?;
+        let shares = sqlx::query_as::<_, Share>(&format!(
+            "SELECT {SHARE_COLUMNS} FROM conversation_share WHERE conversation_id = $1 ORDER BY id DESC"
+        ))
+            .bind(conv_id)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(shares)
+    }
+
+    pub async fn revoke(ctx: &Ctx, mm: &ModelManager, conv_id: i64, share_id: i64) -> Result<()> {
+        ConversationBmc::get_owned(ctx, mm, conv_id).await?;
+        let result = sqlx::query(
+            "UPDATE conversation_share SET revoked_at = COALESCE(revoked_at, NOW()) \
+             WHERE id = $1 AND conversation_id = $2"
+        )
+            .bind(share_id)
+            .bind(conv_id)
+            .execute(mm.db())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::EntityNotFound { entity: "conversation_share", id: share_id });
+        }
+        Ok(())
+    }
+
+    /// Snapshot behind a token, for anonymous viewers. Unknown, expired and
+    /// revoked links are all reported as not found. Sources whose documents
+    /// can no longer be cited are dropped at every view.
+    pub async fn get_public(mm: &ModelManager, token: &str) -> Result<ConversationExport> {
+        let snapshot: Option<(Json<ConversationExport>,)> = sqlx::query_as(
+            "SELECT snapshot FROM conversation_share \
+             WHERE token = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())"
+        )
+            .bind(token)
+            .fetch_optional(mm.db())
+            .await?;
+        let Some((Json(mut snapshot),)) = snapshot else {
+            return Err(Error::EntityNotFound { entity: "conversation_share", id: 0 });
+        };
+
+        let cited: Vec<String> = snapshot
+            .messages
+            .iter()
+            .flat_map(|m| m.sources.iter().filter_map(|d| d.doc_id.clone()))
+            .collect();
+        let citable: HashSet<String> = DocumentBmc::citable_ids(mm, &cited).await?.into_iter().collect();
+        for msg in &mut snapshot.messages {
+            msg.sources
+                .retain(|d| d.doc_id.as_ref().is_some_and(|id| citable.contains(id)));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// 256 random bits, URL-safe.
+fn new_token() -> String {
+    base64_url::encode(&rand::random::<[u8; 32]>())
+}
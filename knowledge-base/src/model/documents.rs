@@ -1,8 +1,12 @@
 use std::path::Path;
+use std::time::Instant;
 use crate::ctx::Ctx;
 use crate::model::base::{self, DbBmc};
 use crate::model::manager::ModelManager;
+use crate::model::prompt::PromptSet;
+use crate::model::stages::{CountingCache, Timed};
 use crate::error::{Error, Result};
+use crate::metrics::{self, IngestJobGuard};
 use serde::{Deserialize, Serialize};
 use modql::field::Fields;
 use sqlx::FromRow;
@@ -13,7 +17,9 @@ use tokio::task::spawn_blocking;
 use num_cpus;
 
 use swiftide::indexing::{EmbedMode, Node, Pipeline};
-use swiftide::indexing::transformers::{ChunkMarkdown, ChunkText, Embed, MetadataKeywords, MetadataQAText, MetadataSummary, MetadataTitle};
+use swiftide::indexing::transformers::{ChunkMarkdown, ChunkText, Embed, MetadataKeywords, MetadataQAText, MetadataSummary, MetadataTitle, SparseEmbed};
+
+const INGEST_PIPELINE: &str = "ingest_document";
 
 #[derive(Debug, Clone, Fields, FromRow, Serialize, Deserialize)]
 pub struct Document {
@@ -80,8 +86,23 @@ impl DocumentBmc {
 
     #[instrument]
     pub async fn delete(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
-        base::delete::<Self>(ctx, mm, id).await
+        base::delete::<Self>(ctx, mm, id).await?;
+        mm.corpus_changed().await;
+        Ok(())
+    }
+
+    /// Which of the given `doc_id`s may still be cited outside the
+    /// conversation they were retrieved in. Documents carry no per-user
+    /// restrictions yet, so this is every document that was not deleted.
+    pub async fn citable_ids(mm: &ModelManager, doc_ids: &[String]) -> Result<Vec<String>> {
+        let ids: Vec<i64> = doc_ids.iter().filter_map(|id| id.parse().ok()).collect();
+        let rows: Vec<(i64,)> = sqlx::query_as("SELECT id FROM document WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(rows.into_iter().map(|(id,)| id.to_string()).collect())
     }
+
     #[instrument]
     pub async fn upload_document(
         ctx: &Ctx,
@@ -113,6 +134,24 @@ impl DocumentBmc {
         Ok(parsed_content)
     }
 
+    async fn load_text(filepath: &str) -> Result<String> {
+        if filepath.ends_with(".pdf") {
+            Self::parse_pdf_blocking(filepath.to_string()).await
+        } else {
+            read_to_string(filepath)
+                .await
+                .map_err(|_| Error::DocumentUploadFail)
+        }
+    }
+
+    /// Document with its full text, as it was indexed.
+    #[instrument]
+    pub async fn read_text(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<(Document, String)> {
+        let document = Self::get(ctx, mm, id).await?;
+        let text = Self::load_text(&document.filepath).await?;
+        Ok((document, text))
+    }
+
     #[instrument]
     pub async fn upload_document_with_embedding(
         ctx: &Ctx,
@@ -120,16 +159,12 @@ impl DocumentBmc {
         doc_internal: DocumentForCreateInternal,
     ) -> Result<Document> {
 
+        let _in_progress = IngestJobGuard::enter();
+
         let document = Self::upload_document(ctx, mm, doc_internal.filename.clone(), doc_internal.filepath.clone()).await?;
         let document_id = document.id;
 
-        let text = if doc_internal.filepath.ends_with(".pdf") {
-            Self::parse_pdf_blocking(doc_internal.filepath.clone()).await?
-        } else {
-            read_to_string(&doc_internal.filepath)
-                .await
-                .map_err(|_| Error::DocumentUploadFail)?
-        };
+        let text = Self::load_text(&doc_internal.filepath).await?;
 
         let node = Node::builder()
             .original_size(text.len())
@@ -140,41 +175,82 @@ impl DocumentBmc {
             .metadata([("doc_uploaded_by", doc_internal.uploaded_by.to_string())])
             .build()
             .map_err(|e| Error::SwiftideError(e.to_string()))?;
+        let llm = mm.llm.prompt();
+        let embed = mm.llm.embed();
+        let prompts = PromptSet::load(ctx, mm).await?;
+        let template = |stage: &str| prompts.get(stage).map(|t| t.to_string());
+        let qa_text = match template("metadata_qa_text") {
+            Some(t) => MetadataQAText::builder().client(llm.clone()).prompt_template(t).build()
+                .map_err(|e| Error::SwiftideError(e.to_string()))?,
+            None => MetadataQAText::new(llm.clone()),
+        };
+        let summary = match template("metadata_summary") {
+            Some(t) => MetadataSummary::builder().client(llm.clone()).prompt_template(t).build()
+                .map_err(|e| Error::SwiftideError(e.to_string()))?,
+            None => MetadataSummary::new(llm.clone()),
+        };
+        let title = match template("metadata_title") {
+            Some(t) => MetadataTitle::builder().client(llm.clone()).prompt_template(t).build()
+                .map_err(|e| Error::SwiftideError(e.to_string()))?,
+            None => MetadataTitle::new(llm.clone()),
+        };
+        let keywords = match template("metadata_keywords") {
+            Some(t) => MetadataKeywords::builder().client(llm.clone()).prompt_template(t).build()
+                .map_err(|e| Error::SwiftideError(e.to_string()))?,
+            None => MetadataKeywords::new(llm.clone()),
+        };
         let pipeline = match Path::new(&doc_internal.filepath).extension().and_then(|ext| ext.to_str()) {
             Some("md") => {
                 Pipeline::from_stream(vec![Ok(node)])
                     .with_concurrency(num_cpus::get() * 2)
                     .with_embed_mode(EmbedMode::Both)
                     .then_chunk(ChunkMarkdown::from_chunk_range(10..2048))
-                    .then(MetadataQAText::new(mm.ollama.clone()))
-                    .then(MetadataSummary::new(mm.ollama.clone()))
-                    .then(MetadataTitle::new(mm.ollama.clone()))
-                    .then(MetadataKeywords::new(mm.ollama.clone()))
-                    .then_in_batch(Embed::new(mm.ollama.clone()).with_batch_size(64))
+                    .then(Timed::new(qa_text, INGEST_PIPELINE, llm.prompt_model(), "metadata_qa_text"))
+                    .then(Timed::new(summary, INGEST_PIPELINE, llm.prompt_model(), "metadata_summary"))
+                    .then(Timed::new(title, INGEST_PIPELINE, llm.prompt_model(), "metadata_title"))
+                    .then(Timed::new(keywords, INGEST_PIPELINE, llm.prompt_model(), "metadata_keywords"))
+                    .then_in_batch(Timed::new(Embed::new(embed.clone()).with_batch_size(64), INGEST_PIPELINE, embed.embed_model(), "embed"))
             },
             Some("txt") | Some("pdf") => {
                 Pipeline::from_stream(vec![Ok(node)])
                     .with_concurrency(num_cpus::get() * 2)
                     .with_embed_mode(EmbedMode::Both)
                     .then_chunk(ChunkText::from_chunk_range(10..2048))
-                    .then(MetadataQAText::new(mm.ollama.clone()))
-                    .then(MetadataSummary::new(mm.ollama.clone()))
-                    .then(MetadataTitle::new(mm.ollama.clone()))
-                    .then(MetadataKeywords::new(mm.ollama.clone()))
-                    .then_in_batch(Embed::new(mm.ollama.clone()).with_batch_size(64))
+                    .then(Timed::new(qa_text, INGEST_PIPELINE, llm.prompt_model(), "metadata_qa_text"))
+                    .then(Timed::new(summary, INGEST_PIPELINE, llm.prompt_model(), "metadata_summary"))
+                    .then(Timed::new(title, INGEST_PIPELINE, llm.prompt_model(), "metadata_title"))
+                    .then(Timed::new(keywords, INGEST_PIPELINE, llm.prompt_model(), "metadata_keywords"))
+                    .then_in_batch(Timed::new(Embed::new(embed.clone()).with_batch_size(64), INGEST_PIPELINE, embed.embed_model(), "embed"))
             },
             _ => {
                 return Err(Error::ServiceError("Unsupported file extension".to_string()));
             }
         };
-        pipeline
+        let pipeline = match (mm.llm.sparse(), mm.llm.sparse_model()) {
+            (Some(sparse), Some(sparse_model)) => pipeline.then_in_batch(Timed::new(
+                SparseEmbed::new(sparse.clone()).with_batch_size(64),
+                INGEST_PIPELINE, sparse_model, "sparse_embed",
+            )),
+            _ => pipeline,
+        };
+        let embed_model = embed.embed_model().to_string();
+        let start = Instant::now();
+        let result = pipeline
             .log_all()
             //.filter_errors()
-            .filter_cached(mm.redis_cache.clone())
+            .filter_cached(CountingCache::new(mm.redis_cache.clone()))
+            .then(move |node: Node| {
+                metrics::record_chunk_indexed(&embed_model);
+                Ok(node)
+            })
             .then_store_with(mm.qdrant.clone())
             .run()
-            .await
-            .map_err(|e| Error::DocumentUploadFailOllama)?;
+            .await;
+        let elapsed = start.elapsed();
+        metrics::record_ingest(llm.prompt_model(), elapsed, result.is_ok());
+        mm.log_pipeline_run(ctx, INGEST_PIPELINE, elapsed.as_millis() as i32, result.is_ok()).await;
+        mm.corpus_changed().await;
+        result.map_err(|e| Error::DocumentUploadFailOllama)?;
 
         Ok(document)
     }
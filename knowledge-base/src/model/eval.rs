@@ -0,0 +1,475 @@
+//! src/model/eval.rs
+//! golden question sets, evaluation runs and their per-question results
+
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use swiftide::prompt::Prompt;
+use swiftide::traits::SimplePrompt;
+use tracing::{instrument, warn};
+
+use crate::model::grounding;
+use crate::model::llm::LlmClient;
+use crate::model::manager::ModelManager;
+use crate::model::pipeline::PipelineParams;
+use crate::{Ctx, Error, Result};
+
+const EVAL_PIPELINE: &str = "eval";
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Data structures                                                          */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct EvalDataset {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct EvalCase {
+    pub id: i64,
+    pub dataset_id: i64,
+    pub question: String,
+    pub expected_answer: Option<String>,
+    pub expected_doc_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvalCaseForCreate {
+    pub question: String,
+    pub expected_answer: Option<String>,
+    #[serde(default)]
+    pub expected_doc_ids: Vec<String>,
+}
+
+/// Pipeline settings a run was executed with.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvalRunConfig {
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub pipeline: PipelineParams,
+    /// Cut-off for recall@k and nDCG@k; all retrieved documents when absent.
+    pub k: Option<usize>,
+    /// Score answers with the prompt model as judge (faithfulness, answer relevance).
+    #[serde(default = "default_true")]
+    pub judge: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Averages over the cases of a run; answer metrics only over judged cases.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvalMetrics {
+    pub cases: usize,
+    pub errors: usize,
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub ndcg_at_k: f64,
+    pub faithfulness: Option<f64>,
+    pub answer_relevance: Option<f64>,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct EvalRun {
+    pub id: i64,
+    pub dataset_id: i64,
+    pub label: Option<String>,
+    pub config: Json<EvalRunConfig>,
+    pub status: String,
+    pub metrics: Option<Json<EvalMetrics>>,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct EvalResult {
+    pub id: i64,
+    pub run_id: i64,
+    pub case_id: i64,
+    pub retrieved_doc_ids: Vec<String>,
+    pub answer: Option<String>,
+    pub recall: Option<f64>,
+    pub mrr: Option<f64>,
+    pub ndcg: Option<f64>,
+    pub faithfulness: Option<f64>,
+    pub answer_relevance: Option<f64>,
+    pub latency_ms: i32,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct EvalResultForInsert {
+    retrieved_doc_ids: Vec<String>,
+    answer: Option<String>,
+    recall: Option<f64>,
+    mrr: Option<f64>,
+    ndcg: Option<f64>,
+    faithfulness: Option<f64>,
+    answer_relevance: Option<f64>,
+    latency_ms: i32,
+    error: Option<String>,
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  BMC                                                                      */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub struct EvalBmc;
+
+impl EvalBmc {
+    pub async fn create_dataset(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        name: &str,
+        description: Option<&str>,
+        cases: Vec<EvalCaseForCreate>,
+    ) -> Result<EvalDataset> {
+        let mut tx = mm.db().begin().await?;
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO eval_dataset (name, description, created_by) VALUES ($1, $2, $3) RETURNING id"
+        )
+            .bind(name)
+            .bind(description)
+            .bind(ctx.user_id())
+            .fetch_one(&mut *tx)
+            .await?;
+        for case in cases {
+            Self::insert_case(&mut tx, id, case).await?;
+        }
+        tx.commit().await?;
+
+        Self::get_dataset(ctx, mm, id).await
+    }
+
+    pub async fn add_cases(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        dataset_id: i64,
+        cases: Vec<EvalCaseForCreate>,
+    ) -> Result<Vec<EvalCase>> {
+        Self::get_dataset(ctx, mm, dataset_id).await?;
+
+        let mut tx = mm.db().begin().await?;
+        for case in cases {
+            Self::insert_case(&mut tx, dataset_id, case).await?;
+        }
+        tx.commit().await?;
+
+        Self::list_cases(ctx, mm, dataset_id).await
+    }
+
+    async fn insert_case(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        dataset_id: i64,
+        case: EvalCaseForCreate,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO eval_case (dataset_id, question, expected_answer, expected_doc_ids) VALUES ($1, $2, $3, $4)"
+        )
+            .bind(dataset_id)
+            .bind(case.question)
+            .bind(case.expected_answer)
+            .bind(case.expected_doc_ids)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_dataset(_ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<EvalDataset> {
+        sqlx::query_as::<_, EvalDataset>(
+            "SELECT id, name, description, created_by, created_at FROM eval_dataset WHERE id = $1"
+        )
+            .bind(id)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or(Error::EntityNotFound { entity: "eval_dataset", id })
+    }
+
+    pub async fn list_datasets(_ctx: &Ctx, mm: &ModelManager) -> Result<Vec<EvalDataset>> {
+        let datasets = sqlx::query_as::<_, EvalDataset>(
+            "SELECT id, name, description, created_by, created_at FROM eval_dataset ORDER BY id DESC"
+        )
+            .fetch_all(mm.db())
+            .await?;
+        Ok(datasets)
+    }
+
+    pub async fn list_cases(_ctx: &Ctx, mm: &ModelManager, dataset_id: i64) -> Result<Vec<EvalCase>> {
+        let cases = sqlx::query_as::<_, EvalCase>(
+            "SELECT id, dataset_id, question, expected_answer, expected_doc_ids FROM eval_case WHERE dataset_id = $1 ORDER BY id"
+        )
+            .bind(dataset_id)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(cases)
+    }
+
+    pub async fn create_run(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        dataset_id: i64,
+        label: Option<&str>,
+        config: EvalRunConfig,
+    ) -> Result<EvalRun> {
+        Self::get_dataset(ctx, mm, dataset_id).await?;
+
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO eval_run (dataset_id, label, config, created_by) VALUES ($1, $2, $3, $4) RETURNING id"
+        )
+            .bind(dataset_id)
+            .bind(label)
+            .bind(Json(config))
+            .bind(ctx.user_id())
+            .fetch_one(mm.db())
+            .await?;
+
+        Self::get_run(ctx, mm, id).await
+    }
+
+    pub async fn get_run(_ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<EvalRun> {
+        sqlx::query_as::<_, EvalRun>(
+            "SELECT id, dataset_id, label, config, status, metrics, created_by, created_at, finished_at \
+             FROM eval_run WHERE id = $1"
+        )
+            .bind(id)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or(Error::EntityNotFound { entity: "eval_run", id })
+    }
+
+    /// Newest first, optionally for a single dataset.
+    pub async fn list_runs(_ctx: &Ctx, mm: &ModelManager, dataset_id: Option<i64>) -> Result<Vec<EvalRun>> {
+        let runs = sqlx::query_as::<_, EvalRun>(
+            "SELECT id, dataset_id, label, config, status, metrics, created_by, created_at, finished_at \
+             FROM eval_run WHERE ($1::BIGINT IS NULL OR dataset_id = $1) ORDER BY id DESC"
+        )
+            .bind(dataset_id)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(runs)
+    }
+
+    pub async fn list_results(_ctx: &Ctx, mm: &ModelManager, run_id: i64) -> Result<Vec<EvalResult>> {
+        let results = sqlx::query_as::<_, EvalResult>(
+            "SELECT id, run_id, case_id, retrieved_doc_ids, answer, recall, mrr, ndcg, faithfulness, \
+             answer_relevance, latency_ms, error FROM eval_result WHERE run_id = $1 ORDER BY case_id"
+        )
+            .bind(run_id)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(results)
+    }
+
+    /// Evaluates every case of the run's dataset and stores the aggregated metrics.
+    /// A failing case is recorded and counted, it does not abort the run.
+    #[instrument(skip(ctx, mm))]
+    pub async fn execute_run(ctx: &Ctx, mm: &ModelManager, run_id: i64) -> Result<EvalMetrics> {
+        let run = Self::get_run(ctx, mm, run_id).await?;
+        let config = run.config.0;
+        let cases = Self::list_cases(ctx, mm, run.dataset_id).await?;
+        let judge = match config.judge {
+            true => Some(mm.llm.prompt_for(config.model.as_deref())?),
+            false => None,
+        };
+
+        let mut results = Vec::with_capacity(cases.len());
+        for case in &cases {
+            let result = Self::evaluate_case(ctx, mm, &config, judge.as_ref(), case).await;
+            Self::insert_result(mm, run_id, case.id, &result).await?;
+            results.push(result);
+        }
+
+        let metrics = aggregate(&results);
+        sqlx::query("UPDATE eval_run SET status = 'done', metrics = $2, finished_at = NOW() WHERE id = $1")
+            .bind(run_id)
+            .bind(Json(&metrics))
+            .execute(mm.db())
+            .await?;
+        Ok(metrics)
+    }
+
+    pub async fn fail_run(_ctx: &Ctx, mm: &ModelManager, run_id: i64) -> Result<()> {
+        sqlx::query("UPDATE eval_run SET status = 'error', finished_at = NOW() WHERE id = $1")
+            .bind(run_id)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+
+    async fn evaluate_case(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        config: &EvalRunConfig,
+        judge: Option<&LlmClient>,
+        case: &EvalCase,
+    ) -> EvalResultForInsert {
+        let start = Instant::now();
+        let answered = mm
+            .answer_with_sources(ctx, EVAL_PIPELINE, &case.question, config.model.as_deref(), &config.pipeline)
+            .await;
+        let latency_ms = start.elapsed().as_millis() as i32;
+
+        let answered = match answered {
+            Ok(answered) => answered,
+            Err(e) => {
+                warn!("eval case {} failed: {e}", case.id);
+                return EvalResultForInsert {
+                    latency_ms,
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                };
+            }
+        };
+
+        let retrieved_doc_ids = unique_doc_ids(answered.documents.iter().filter_map(|d| d.doc_id.clone()));
+        let k = config.k.unwrap_or(retrieved_doc_ids.len());
+        let (faithfulness, answer_relevance) = match judge {
+            Some(judge) => (
+                judge_faithfulness(judge, &answered.contexts, &answered.answer).await,
+                judge_answer_relevance(judge, &case.question, &answered.answer).await,
+            ),
+            None => (None, None),
+        };
+
+        let has_expected = !case.expected_doc_ids.is_empty();
+        EvalResultForInsert {
+            recall: has_expected.then(|| recall_at_k(&retrieved_doc_ids, &case.expected_doc_ids, k)),
+            mrr: has_expected.then(|| reciprocal_rank(&retrieved_doc_ids, &case.expected_doc_ids)),
+            ndcg: has_expected.then(|| ndcg_at_k(&retrieved_doc_ids, &case.expected_doc_ids, k)),
+            retrieved_doc_ids,
+            answer: Some(answered.answer),
+            faithfulness,
+            answer_relevance,
+            latency_ms,
+            error: None,
+        }
+    }
+
+    async fn insert_result(mm: &ModelManager, run_id: i64, case_id: i64, r: &EvalResultForInsert) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO eval_result (run_id, case_id, retrieved_doc_ids, answer, recall, mrr, ndcg, \
+             faithfulness, answer_relevance, latency_ms, error) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+        )
+            .bind(run_id)
+            .bind(case_id)
+            .bind(&r.retrieved_doc_ids)
+            .bind(&r.answer)
+            .bind(r.recall)
+            .bind(r.mrr)
+            .bind(r.ndcg)
+            .bind(r.faithfulness)
+            .bind(r.answer_relevance)
+            .bind(r.latency_ms)
+            .bind(&r.error)
+            .execute(mm.db())
+            .await?;
+        Ok(())
+    }
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Metrics                                                                  */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+/// Chunks of one document share its id; keep the first (best ranked) occurrence.
+pub fn unique_doc_ids(ids: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for id in ids {
+        if !unique.contains(&id) {
+            unique.push(id);
+        }
+    }
+    unique
+}
+
+pub fn recall_at_k(retrieved: &[String], expected: &[String], k: usize) -> f64 {
+    if expected.is_empty() {
+        return 0.0;
+    }
+    let found = expected.iter().filter(|e| retrieved.iter().take(k).any(|r| r == *e)).count();
+    found as f64 / expected.len() as f64
+}
+
+pub fn reciprocal_rank(retrieved: &[String], expected: &[String]) -> f64 {
+    retrieved
+        .iter()
+        .position(|r| expected.contains(r))
+        .map(|pos| 1.0 / (pos + 1) as f64)
+        .unwrap_or(0.0)
+}
+
+/// Binary relevance nDCG: every expected document counts as fully relevant.
+pub fn ndcg_at_k(retrieved: &[String], expected: &[String], k: usize) -> f64 {
+    let discount = |rank: usize| 1.0 / ((rank + 2) as f64).log2();
+    let dcg: f64 = retrieved
+        .iter()
+        .take(k)
+        .enumerate()
+        .filter(|(_, r)| expected.contains(r))
+        .map(|(rank, _)| discount(rank))
+        .sum();
+    let idcg: f64 = (0..expected.len().min(k)).map(discount).sum();
+    if idcg == 0.0 { 0.0 } else { dcg / idcg }
+}
+
+fn aggregate(results: &[EvalResultForInsert]) -> EvalMetrics {
+    let mean = |values: Vec<f64>| match values.len() {
+        0 => None,
+        n => Some(values.iter().sum::<f64>() / n as f64),
+    };
+    let ok: Vec<&EvalResultForInsert> = results.iter().filter(|r| r.error.is_none()).collect();
+
+    EvalMetrics {
+        cases: results.len(),
+        errors: results.len() - ok.len(),
+        recall_at_k: mean(ok.iter().filter_map(|r| r.recall).collect()).unwrap_or_default(),
+        mrr: mean(ok.iter().filter_map(|r| r.mrr).collect()).unwrap_or_default(),
+        ndcg_at_k: mean(ok.iter().filter_map(|r| r.ndcg).collect()).unwrap_or_default(),
+        faithfulness: mean(ok.iter().filter_map(|r| r.faithfulness).collect()),
+        answer_relevance: mean(ok.iter().filter_map(|r| r.answer_relevance).collect()),
+        avg_latency_ms: mean(results.iter().map(|r| r.latency_ms as f64).collect()).unwrap_or_default(),
+    }
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  LLM judge                                                                */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+async fn judge_faithfulness(judge: &LlmClient, contexts: &[String], answer: &str) -> Option<f64> {
+    grounding::groundedness(judge, None, contexts, answer).await
+}
+
+async fn judge_answer_relevance(judge: &LlmClient, question: &str, answer: &str) -> Option<f64> {
+    let prompt = format!(
+        "You grade answers to questions.\n\
+         Question:\n{question}\n\nAnswer:\n{answer}\n\n\
+         How well does the answer address the question? \
+         Reply with a single number between 0 (not at all) and 1 (completely).",
+    );
+    judge_score(judge, prompt).await
+}
+
+/// First number in the judge's reply, clamped to [0, 1]; `None` when the judge
+/// fails or does not answer with a number.
+async fn judge_score(judge: &LlmClient, prompt: String) -> Option<f64> {
+    let reply = match judge.prompt(Prompt::from(prompt)).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            warn!("eval judge failed: {e}");
+            return None;
+        }
+    };
+    grounding::parse_score(&reply)
+}
@@ -0,0 +1,240 @@
+//! src/model/feedback.rs
+//! thumbs up/down on assistant answers, and reports of the low-rated ones
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+
+use crate::model::agent::AgentStep;
+use crate::model::chat::{Access, ConversationBmc};
+use crate::model::eval::{EvalBmc, EvalCase, EvalCaseForCreate};
+use crate::model::manager::ModelManager;
+use crate::model::pipeline::ResolvedPipeline;
+use crate::model::retrieval::RetrievedDocument;
+use crate::{Ctx, Error, Result};
+
+/// Reports return at most this many answers.
+const MAX_REPORT_ROWS: i64 = 500;
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Data structures                                                          */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    #[default]
+    Down,
+}
+
+impl Rating {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rating::Up => "up",
+            Rating::Down => "down",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackForCreate {
+    pub rating: Rating,
+    pub comment: Option<String>,
+    /// `doc_id`s of retrieved sources the user considers wrong or irrelevant.
+    #[serde(default)]
+    pub flagged_sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Feedback {
+    pub id: i64,
+    pub message_id: i64,
+    pub user_id: i64,
+    pub rating: String,
+    pub comment: Option<String>,
+    pub flagged_sources: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Rated answer with the question, config and chunks it was produced from.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct FeedbackReport {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub feedback: Feedback,
+    pub conversation_id: i64,
+    /// User prompt the answer replied to.
+    pub question: Option<String>,
+    pub answer: String,
+    pub confidence: Option<f64>,
+    pub grounded: Option<bool>,
+    /// Missing for answers stored before traces were recorded.
+    pub pipeline: Option<Json<ResolvedPipeline>>,
+    pub documents: Option<Json<Vec<RetrievedDocument>>>,
+    pub contexts: Option<Vec<String>>,
+    /// Tool calls, for answers given in agent mode.
+    pub agent_steps: Option<Json<Vec<AgentStep>>>,
+}
+
+/// Ratings per pipeline preset; `preset` is null for ad hoc configs.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct FeedbackSummary {
+    pub preset: Option<String>,
+    pub up: i64,
+    pub down: i64,
+    /// Ratings that flagged at least one source.
+    pub flagged: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FeedbackFilter {
+    /// `down` when absent.
+    #[serde(default)]
+    pub rating: Rating,
+    pub since: Option<DateTime<Utc>>,
+    pub preset: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  BMC                                                                      */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub struct FeedbackBmc;
+
+impl FeedbackBmc {
+    /// Rates an assistant answer in a conversation the user can read. Rating
+    /// the same answer again replaces the earlier feedback.
+    pub async fn upsert(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        message_id: i64,
+        feedback_c: FeedbackForCreate,
+    ) -> Result<Feedback> {
+        let (conversation_id, sender): (i64, String) = sqlx::query_as(
+            "SELECT conversation_id, sender FROM message WHERE id = $1"
+        )
+            .bind(message_id)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or(Error::EntityNotFound { entity: "message", id: message_id })?;
+        // anyone who can read the conversation may rate its answers
+        ConversationBmc::require(ctx, mm, conversation_id, Access::Read).await?;
+        if sender != "assistant" {
+            return Err(Error::FeedbackInvalid("only assistant answers can be rated".to_string()));
+        }
+        Self::validate_flagged_sources(mm, message_id, &feedback_c.flagged_sources).await?;
+
+        let feedback = sqlx::query_as::<_, Feedback>(
+            "INSERT INTO message_feedback (message_id, user_id, rating, comment, flagged_sources) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (message_id, user_id) DO UPDATE SET rating = EXCLUDED.rating, comment = EXCLUDED.comment, \
+             flagged_sources = EXCLUDED.flagged_sources, updated_at = NOW() \
+             RETURNING id, message_id, user_id, rating, comment, flagged_sources, created_at, updated_at"
+        )
+            .bind(message_id)
+            .bind(ctx.user_id())
+            .bind(feedback_c.rating.as_str())
+            .bind(feedback_c.comment)
+            .bind(feedback_c.flagged_sources)
+            .fetch_one(mm.db())
+            .await?;
+        Ok(feedback)
+    }
+
+    /// Flagged sources must be among the documents the answer was based on.
+    async fn validate_flagged_sources(mm: &ModelManager, message_id: i64, flagged: &[String]) -> Result<()> {
+        if flagged.is_empty() {
+            return Ok(());
+        }
+        let documents: Option<(Json<Vec<RetrievedDocument>>,)> =
+            sqlx::query_as("SELECT documents FROM message_trace WHERE message_id = $1")
+                .bind(message_id)
+                .fetch_optional(mm.db())
+                .await?;
+        let Some((Json(documents),)) = documents else {
+            return Err(Error::FeedbackInvalid("this answer has no recorded sources".to_string()));
+        };
+        let known: HashSet<&str> = documents.iter().filter_map(|d| d.doc_id.as_deref()).collect();
+        match flagged.iter().find(|id| !known.contains(id.as_str())) {
+            Some(id) => Err(Error::FeedbackInvalid(format!("{id} is not a source of this answer"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Rated answers matching `filter`, newest first.
+    pub async fn list_reports(_ctx: &Ctx, mm: &ModelManager, filter: &FeedbackFilter) -> Result<Vec<FeedbackReport>> {
+        let limit = filter.limit.unwrap_or(100).clamp(1, MAX_REPORT_ROWS);
+        let reports = sqlx::query_as::<_, FeedbackReport>(
+            "SELECT f.id, f.message_id, f.user_id, f.rating, f.comment, f.flagged_sources, f.created_at, f.updated_at, \
+                    m.conversation_id, q.content AS question, m.content AS answer, m.confidence, m.grounded, \
+                    t.pipeline, t.documents, t.contexts, t.agent_steps \
+             FROM message_feedback f \
+             JOIN message m ON m.id = f.message_id \
+             LEFT JOIN message_trace t ON t.message_id = m.id \
+             LEFT JOIN message q ON q.id = m.parent_id \
+             WHERE f.rating = $1 \
+               AND ($2::timestamptz IS NULL OR f.created_at >= $2) \
+               AND ($3::text IS NULL OR t.pipeline->>'preset' = $3) \
+             ORDER BY f.created_at DESC LIMIT $4"
+        )
+            .bind(filter.rating.as_str())
+            .bind(filter.since)
+            .bind(filter.preset.as_deref())
+            .bind(limit)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(reports)
+    }
+
+    pub async fn summary(_ctx: &Ctx, mm: &ModelManager, since: Option<DateTime<Utc>>) -> Result<Vec<FeedbackSummary>> {
+        let summary = sqlx::query_as::<_, FeedbackSummary>(
+            "SELECT t.pipeline->>'preset' AS preset, \
+                    COUNT(*) FILTER (WHERE f.rating = 'up') AS up, \
+                    COUNT(*) FILTER (WHERE f.rating = 'down') AS down, \
+                    COUNT(*) FILTER (WHERE cardinality(f.flagged_sources) > 0) AS flagged \
+             FROM message_feedback f \
+             LEFT JOIN message_trace t ON t.message_id = f.message_id \
+             WHERE ($1::timestamptz IS NULL OR f.created_at >= $1) \
+             GROUP BY 1 ORDER BY 1 NULLS FIRST"
+        )
+            .bind(since)
+            .fetch_all(mm.db())
+            .await?;
+        Ok(summary)
+    }
+
+    /// Adds the questions of the reported answers to an eval dataset, once
+    /// each. Expected answers and documents are left for a reviewer to fill in.
+    pub async fn export_to_dataset(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        dataset_id: i64,
+        filter: &FeedbackFilter,
+    ) -> Result<Vec<EvalCase>> {
+        let existing: HashSet<String> = EvalBmc::list_cases(ctx, mm, dataset_id)
+            .await?
+            .into_iter()
+            .map(|c| c.question)
+            .collect();
+        let mut seen = HashSet::new();
+        let cases = Self::list_reports(ctx, mm, filter)
+            .await?
+            .into_iter()
+            .filter_map(|r| r.question)
+            .filter(|q| !existing.contains(q) && seen.insert(q.clone()))
+            .map(|question| EvalCaseForCreate {
+                question,
+                expected_answer: None,
+                expected_doc_ids: Vec::new(),
+            })
+            .collect();
+
+        EvalBmc::add_cases(ctx, mm, dataset_id, cases).await
+    }
+}
@@ -0,0 +1,80 @@
+//! Post-answer check that the answer's claims are supported by the retrieved
+//! contexts. Shared by the query pipelines and the eval faithfulness judge.
+
+use swiftide::prompt::Prompt;
+use swiftide::traits::SimplePrompt;
+use tracing::warn;
+
+use crate::model::llm::LlmClient;
+
+const GROUNDEDNESS_PROMPT: &str = "You verify answers produced from retrieved context.\n\
+Context:\n{{ context }}\n\nAnswer:\n{{ answer }}\n\n\
+List every factual claim of the answer on its own line, each followed by SUPPORTED or UNSUPPORTED \
+depending on whether the context states it. Then end with a line `SCORE: <n>` where n is the share \
+of supported claims, between 0 (nothing supported) and 1 (fully supported).";
+
+/// Share of the answer's claims supported by `contexts`, between 0 and 1.
+/// `None` when the model fails or replies without a score.
+pub async fn groundedness(
+    llm: &LlmClient,
+    template: Option<&str>,
+    contexts: &[String],
+    answer: &str,
+) -> Option<f64> {
+    let prompt = Prompt::from(template.unwrap_or(GROUNDEDNESS_PROMPT).to_string())
+        .with_context_value("context", contexts.join("\n---\n"))
+        .with_context_value("answer", answer);
+    let reply = match llm.prompt(prompt).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            warn!("groundedness check failed: {e}");
+            return None;
+        }
+    };
+    let score = reported_score(&reply);
+    if score.is_none() {
+        warn!("groundedness check replied without a SCORE line");
+    }
+    score
+}
+
+/// Score of the last `SCORE: <n>` line of `reply`, clamped to [0, 1]. Other
+/// numbers (claim numbering, figures quoted from the answer) are ignored.
+pub fn reported_score(reply: &str) -> Option<f64> {
+    reply.lines().rev().find_map(|line| {
+        let (label, value) = line.trim().trim_start_matches(['*', '`']).split_once(':')?;
+        label.trim().eq_ignore_ascii_case("score").then(|| parse_score(value)).flatten()
+    })
+}
+
+/// First number in `reply`, clamped to [0, 1].
+pub fn parse_score(reply: &str) -> Option<f64> {
+    reply
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .find_map(|token| token.parse::<f64>().ok())
+        .map(|score| score.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_score_line() {
+        let reply = "1. The VPN needs a token SUPPORTED\n2. It costs 3 dollars UNSUPPORTED\nSCORE: 0.5";
+        assert_eq!(reported_score(reply), Some(0.5));
+        assert_eq!(reported_score("**Score:** 1"), Some(1.0));
+        assert_eq!(reported_score("SCORE: 1.7"), Some(1.0));
+    }
+
+    #[test]
+    fn numbered_claims_without_a_score_line_have_no_score() {
+        let reply = "1. The VPN requires a hardware token SUPPORTED\n2. Access is granted in 2 days UNSUPPORTED";
+        assert_eq!(reported_score(reply), None);
+    }
+
+    #[test]
+    fn score_line_without_a_number_has_no_score() {
+        assert_eq!(reported_score("SCORE: unknown"), None);
+    }
+}
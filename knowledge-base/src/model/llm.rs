@@ -0,0 +1,559 @@
+//! Prompt and embedding model providers, selected through `Config`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_openai::config::OpenAIConfig;
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+    ChatCompletionToolArgs, ChatCompletionToolType, CreateChatCompletionRequestArgs, FunctionCall, FunctionObjectArgs,
+};
+use async_openai::Client as OpenAIClient;
+use async_trait::async_trait;
+use fastembed::{InitOptions, ModelInfo, SparseInitOptions, SparseTextEmbedding, TextEmbedding};
+use futures_util::StreamExt;
+use swiftide::integrations::fastembed::FastEmbed;
+use swiftide::integrations::ollama::config::OllamaConfig;
+use swiftide::integrations::ollama::Ollama;
+use swiftide::integrations::openai::OpenAI;
+use swiftide::prompt::Prompt;
+use swiftide::traits::{EmbeddingModel, SimplePrompt};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::model::rerank::Reranker;
+use crate::{Error, Result};
+
+/// Vector size of `bge-m3`, the default embedding model. Used by the stub
+/// embedder and when the embedding model cannot be probed at startup, unless
+/// `SERVICE_EMBED_DIMENSIONS` is set.
+const DEFAULT_EMBED_DIMENSIONS: usize = 1024;
+const STUB_ANSWER_MAX_CHARS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProvider {
+    Ollama,
+    /// Any server speaking the OpenAI API (OpenAI, vLLM, llama.cpp server, ...).
+    OpenAiCompatible,
+    /// CPU-only in-process embeddings; cannot be used for prompting.
+    FastEmbed,
+    /// Deterministic in-process model, for tests and CI.
+    Stub,
+}
+
+impl FromStr for LlmProvider {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ollama" => Ok(Self::Ollama),
+            "openai" | "openai-compatible" | "vllm" | "llamacpp" => Ok(Self::OpenAiCompatible),
+            "fastembed" => Ok(Self::FastEmbed),
+            "stub" => Ok(Self::Stub),
+            other => Err(Error::LlmProviderUnknown(other.to_string())),
+        }
+    }
+}
+
+/// Everything needed to (re)build a client for another model of the same provider.
+#[derive(Debug, Clone)]
+struct ProviderSettings {
+    provider: LlmProvider,
+    base_url: String,
+    api_key: Option<String>,
+    cache_dir: Option<PathBuf>,
+    /// Vector size of the stub embedder, the same as the collection's.
+    stub_dimensions: usize,
+}
+
+impl ProviderSettings {
+    fn new(config: &Config, provider: &str, base_url: &Option<String>, api_key: &Option<String>) -> Result<Self> {
+        let provider: LlmProvider = provider.parse()?;
+        let base_url = match (provider, base_url) {
+            (_, Some(url)) => url.clone(),
+            (LlmProvider::Ollama, None) => format!("{}/v1", config.OLLAMA_URL.trim_end_matches('/')),
+            (LlmProvider::OpenAiCompatible, None) => "https://api.openai.com/v1".to_string(),
+            (LlmProvider::FastEmbed | LlmProvider::Stub, None) => String::new(),
+        };
+
+        Ok(Self {
+            provider,
+            base_url,
+            api_key: api_key.clone(),
+            cache_dir: config.FASTEMBED_CACHE_DIR.as_ref().map(PathBuf::from),
+            stub_dimensions: config.EMBED_DIMENSIONS.unwrap_or(DEFAULT_EMBED_DIMENSIONS),
+        })
+    }
+
+    fn build(&self, prompt_model: &str, embed_model: &str) -> Result<LlmClient> {
+        let backend = match self.provider {
+            LlmProvider::Ollama => {
+                let mut cfg = OllamaConfig::default();
+                cfg.with_api_base(&self.base_url);
+
+                Backend::Ollama(Ollama::builder()
+                    .client(OpenAIClient::with_config(cfg))
+                    .default_prompt_model(prompt_model)
+                    .default_embed_model(embed_model)
+                    .build()?)
+            }
+            LlmProvider::OpenAiCompatible => {
+                let mut cfg = OpenAIConfig::new().with_api_base(&self.base_url);
+                if let Some(key) = &self.api_key {
+                    cfg = cfg.with_api_key(key);
+                }
+
+                Backend::OpenAi(OpenAI::builder()
+                    .client(OpenAIClient::with_config(cfg))
+                    .default_prompt_model(prompt_model)
+                    .default_embed_model(embed_model)
+                    .build()?)
+            }
+            LlmProvider::FastEmbed => {
+                let model = fastembed_model(TextEmbedding::list_supported_models(), embed_model)?;
+                let mut options = InitOptions::new(model);
+                if let Some(dir) = &self.cache_dir {
+                    options = options.with_cache_dir(dir.clone());
+                }
+                let text_embedding = TextEmbedding::try_new(options)
+                    .map_err(|e| Error::LlmError(e.to_string()))?;
+
+                Backend::FastEmbed(FastEmbed::builder()
+                    .embedding_model(text_embedding)
+                    .build()
+                    .map_err(|e| Error::LlmError(e.to_string()))?)
+            }
+            LlmProvider::Stub => Backend::Stub(StubLlm {
+                dimensions: self.stub_dimensions,
+            }),
+        };
+
+        // both speak the OpenAI chat API, which can stream
+        let chat_client = match self.provider {
+            LlmProvider::Ollama | LlmProvider::OpenAiCompatible => {
+                let mut cfg = OpenAIConfig::new().with_api_base(&self.base_url);
+                if let Some(key) = &self.api_key {
+                    cfg = cfg.with_api_key(key);
+                }
+                Some(OpenAIClient::with_config(cfg))
+            }
+            LlmProvider::FastEmbed | LlmProvider::Stub => None,
+        };
+
+        Ok(LlmClient {
+            backend,
+            chat_client,
+            prompt_model: prompt_model.to_string(),
+            embed_model: embed_model.to_string(),
+        })
+    }
+}
+
+/// A Swiftide-compatible client for one provider and one pair of models.
+#[derive(Debug, Clone)]
+pub struct LlmClient {
+    backend: Backend,
+    /// OpenAI chat API client for streamed prompts and tool calls, which
+    /// Swiftide's prompt clients do not offer.
+    chat_client: Option<OpenAIClient<OpenAIConfig>>,
+    prompt_model: String,
+    embed_model: String,
+}
+
+#[derive(Debug, Clone)]
+enum Backend {
+    Ollama(Ollama),
+    OpenAi(OpenAI),
+    FastEmbed(FastEmbed),
+    Stub(StubLlm),
+}
+
+impl LlmClient {
+    pub fn prompt_model(&self) -> &str {
+        &self.prompt_model
+    }
+
+    pub fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    /// Like `prompt`, handing each piece of the reply to `on_delta` as it
+    /// arrives. Returns the whole reply.
+    pub async fn prompt_streaming(
+        &self,
+        prompt: Prompt,
+        on_delta: impl Fn(&str) + Send + Sync,
+    ) -> anyhow::Result<String> {
+        let Some(client) = &self.chat_client else {
+            // the stub answers at once; hand it out word by word all the same
+            let reply = self.prompt(prompt).await?;
+            reply.split_inclusive(' ').for_each(&on_delta);
+            return Ok(reply);
+        };
+
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(&self.prompt_model)
+            .messages([ChatCompletionRequestUserMessageArgs::default()
+                .content(prompt.render().await?)
+                .build()?
+                .into()])
+            .build()?;
+        let mut stream = client.chat().create_stream(request).await?;
+        let mut reply = String::new();
+        while let Some(chunk) = stream.next().await {
+            for choice in chunk?.choices {
+                if let Some(delta) = choice.delta.content {
+                    on_delta(&delta);
+                    reply.push_str(&delta);
+                }
+            }
+        }
+        Ok(reply)
+    }
+}
+
+/// Message of a tool-calling exchange with the prompt model.
+#[derive(Debug, Clone)]
+pub enum LlmMessage {
+    System(String),
+    User(String),
+    Assistant { content: Option<String>, tool_calls: Vec<ToolCall> },
+    Tool { call_id: String, content: String },
+}
+
+/// Function the model may call, with a JSON schema of its arguments.
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// JSON, as written by the model.
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LlmReply {
+    pub content: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl LlmClient {
+    /// One turn of a conversation in which the model may call `tools`
+    /// instead of replying. The stub model never calls tools.
+    pub async fn chat(&self, messages: &[LlmMessage], tools: &[ToolSpec]) -> anyhow::Result<LlmReply> {
+        let Some(client) = &self.chat_client else {
+            let last_user = messages.iter().rev().find_map(|m| match m {
+                LlmMessage::User(text) => Some(text.clone()),
+                _ => None,
+            });
+            let reply = self.prompt(Prompt::from(last_user.unwrap_or_default())).await?;
+            return Ok(LlmReply { content: Some(reply), tool_calls: Vec::new() });
+        };
+
+        let messages = messages
+            .iter()
+            .map(request_message)
+            .collect::<anyhow::Result<Vec<ChatCompletionRequestMessage>>>()?;
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request.model(&self.prompt_model).messages(messages);
+        if !tools.is_empty() {
+            let tools = tools
+                .iter()
+                .map(|t| {
+                    ChatCompletionToolArgs::default()
+                        .r#type(ChatCompletionToolType::Function)
+                        .function(FunctionObjectArgs::default()
+                            .name(t.name)
+                            .description(t.description)
+                            .parameters(t.parameters.clone())
+                            .build()?)
+                        .build()
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            request.tools(tools);
+        }
+
+        let response = client.chat().create(request.build()?).await?;
+        let message = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("the model returned no choices"))?
+            .message;
+        Ok(LlmReply {
+            content: message.content,
+            tool_calls: message
+                .tool_calls
+                .unwrap_or_default()
+                .into_iter()
+                .map(|c| ToolCall { id: c.id, name: c.function.name, arguments: c.function.arguments })
+                .collect(),
+        })
+    }
+}
+
+fn request_message(message: &LlmMessage) -> anyhow::Result<ChatCompletionRequestMessage> {
+    Ok(match message {
+        LlmMessage::System(text) => ChatCompletionRequestSystemMessageArgs::default()
+            .content(text.as_str())
+            .build()?
+            .into(),
+        LlmMessage::User(text) => ChatCompletionRequestUserMessageArgs::default()
+            .content(text.as_str())
+            .build()?
+            .into(),
+        LlmMessage::Assistant { content, tool_calls } => {
+            let mut args = ChatCompletionRequestAssistantMessageArgs::default();
+            if let Some(content) = content {
+                args.content(content.as_str());
+            }
+            if !tool_calls.is_empty() {
+                args.tool_calls(tool_calls
+                    .iter()
+                    .map(|c| ChatCompletionMessageToolCall {
+                        id: c.id.clone(),
+                        r#type: ChatCompletionToolType::Function,
+                        function: FunctionCall { name: c.name.clone(), arguments: c.arguments.clone() },
+                    })
+                    .collect::<Vec<_>>());
+            }
+            args.build()?.into()
+        }
+        LlmMessage::Tool { call_id, content } => ChatCompletionRequestToolMessageArgs::default()
+            .content(content.as_str())
+            .tool_call_id(call_id.as_str())
+            .build()?
+            .into(),
+    })
+}
+
+#[async_trait]
+impl SimplePrompt for LlmClient {
+    async fn prompt(&self, prompt: Prompt) -> anyhow::Result<String> {
+        match &self.backend {
+            Backend::Ollama(c) => c.prompt(prompt).await,
+            Backend::OpenAi(c) => c.prompt(prompt).await,
+            Backend::FastEmbed(_) => anyhow::bail!("fastembed models cannot be prompted"),
+            Backend::Stub(c) => c.prompt(prompt).await,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for LlmClient {
+    async fn embed(&self, input: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+        match &self.backend {
+            Backend::Ollama(c) => c.embed(input).await,
+            Backend::OpenAi(c) => c.embed(input).await,
+            Backend::FastEmbed(c) => c.embed(input).await,
+            Backend::Stub(c) => c.embed(input).await,
+        }
+    }
+}
+
+/// Deterministic stand-in for a real model: answers echo the prompt and
+/// embeddings are hashed bags of words, so similar texts stay close.
+#[derive(Debug, Clone)]
+pub struct StubLlm {
+    dimensions: usize,
+}
+
+#[async_trait]
+impl SimplePrompt for StubLlm {
+    async fn prompt(&self, prompt: Prompt) -> anyhow::Result<String> {
+        let rendered = prompt.render().await?;
+        let last_line = rendered
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or_default()
+            .trim();
+
+        Ok(last_line.chars().take(STUB_ANSWER_MAX_CHARS).collect())
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for StubLlm {
+    async fn embed(&self, input: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>> {
+        Ok(input.iter().map(|text| self.embed_one(text)).collect())
+    }
+}
+
+impl StubLlm {
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; self.dimensions];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let mut hasher = DefaultHasher::new();
+            word.to_lowercase().hash(&mut hasher);
+            vector[(hasher.finish() % self.dimensions as u64) as usize] += 1.0;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+}
+
+/// Prompt and embedding clients built from `Config`, plus the per-request
+/// models callers are allowed to switch to.
+#[derive(Debug, Clone)]
+pub struct LlmRegistry {
+    prompt: LlmClient,
+    embed: LlmClient,
+    sparse: Option<FastEmbed>,
+    sparse_model: Option<String>,
+    reranker: Option<Reranker>,
+    embed_dimensions: Option<usize>,
+    prompt_settings: ProviderSettings,
+    allowed_models: Vec<String>,
+}
+
+impl LlmRegistry {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let prompt_settings = ProviderSettings::new(
+            config, &config.LLM_PROVIDER, &config.LLM_BASE_URL, &config.LLM_API_KEY,
+        )?;
+        if prompt_settings.provider == LlmProvider::FastEmbed {
+            return Err(Error::ConfigWrongFormat("SERVICE_LLM_PROVIDER"));
+        }
+        let embed_settings = ProviderSettings::new(
+            config, &config.EMBED_PROVIDER, &config.EMBED_BASE_URL, &config.EMBED_API_KEY,
+        )?;
+
+        let prompt = prompt_settings.build(&config.PROMPT_MODEL, &config.EMBED_MODEL)?;
+        let embed = embed_settings.build(&config.PROMPT_MODEL, &config.EMBED_MODEL)?;
+
+        let mut allowed_models = config.LLM_ALLOWED_MODELS.clone();
+        if !allowed_models.contains(&config.PROMPT_MODEL) {
+            allowed_models.insert(0, config.PROMPT_MODEL.clone());
+        }
+
+        let sparse = config.SPARSE_EMBED_MODEL
+            .as_deref()
+            .map(|model| build_sparse(model, &embed_settings.cache_dir))
+            .transpose()?;
+
+        let reranker = config.RERANK_MODEL
+            .as_deref()
+            .map(|model| Reranker::try_new(model, embed_settings.cache_dir.clone(), config.RERANK_TOP_N))
+            .transpose()?;
+
+        // Known up front for fastembed; other providers are probed by `embed_dimensions`.
+        let embed_dimensions = config.EMBED_DIMENSIONS.or_else(|| match embed_settings.provider {
+            LlmProvider::FastEmbed => TextEmbedding::list_supported_models()
+                .into_iter()
+                .find(|m| m.model_code.eq_ignore_ascii_case(&config.EMBED_MODEL))
+                .map(|m| m.dim),
+            LlmProvider::Stub => Some(embed_settings.stub_dimensions),
+            _ => None,
+        });
+
+        Ok(Self {
+            prompt,
+            embed,
+            sparse,
+            sparse_model: config.SPARSE_EMBED_MODEL.clone(),
+            reranker,
+            embed_dimensions,
+            prompt_settings,
+            allowed_models,
+        })
+    }
+
+    /// Default client for prompting (subquestions, summaries, answers, metadata).
+    pub fn prompt(&self) -> &LlmClient {
+        &self.prompt
+    }
+
+    /// Client used for every embedding, at indexing and query time alike.
+    pub fn embed(&self) -> &LlmClient {
+        &self.embed
+    }
+
+    /// Sparse (SPLADE-style) embedder, when `SERVICE_SPARSE_EMBED_MODEL` is set.
+    pub fn sparse(&self) -> Option<&FastEmbed> {
+        self.sparse.as_ref()
+    }
+
+    pub fn sparse_model(&self) -> Option<&str> {
+        self.sparse_model.as_deref()
+    }
+
+    /// Cross-encoder reranker, when `SERVICE_RERANK_MODEL` is set.
+    pub fn reranker(&self) -> Option<&Reranker> {
+        self.reranker.as_ref()
+    }
+
+    /// Size of the dense vectors, embedding a probe text when the model does not
+    /// advertise it and falling back to the `bge-m3` size when the probe fails.
+    pub async fn embed_dimensions(&self) -> usize {
+        if let Some(dimensions) = self.embed_dimensions {
+            return dimensions;
+        }
+
+        let probe = self.embed.embed(vec!["dimension probe".to_string()]).await;
+        match probe.map(|vectors| vectors.first().map(Vec::len)) {
+            Ok(Some(dimensions)) => dimensions,
+            Ok(None) => {
+                warn!("embedding probe returned no vector, assuming {DEFAULT_EMBED_DIMENSIONS} dimensions");
+                DEFAULT_EMBED_DIMENSIONS
+            }
+            Err(e) => {
+                warn!("embedding model unreachable, assuming {DEFAULT_EMBED_DIMENSIONS} dimensions: {e}");
+                DEFAULT_EMBED_DIMENSIONS
+            }
+        }
+    }
+
+    /// Prompt client for a per-request or per-conversation model, falling back to the default.
+    pub fn prompt_for(&self, model: Option<&str>) -> Result<LlmClient> {
+        match model {
+            None => Ok(self.prompt.clone()),
+            Some(model) if model == self.prompt.prompt_model() => Ok(self.prompt.clone()),
+            Some(model) if self.allowed_models.iter().any(|m| m == model) => {
+                self.prompt_settings.build(model, self.prompt.embed_model())
+            }
+            Some(model) => Err(Error::LlmModelNotAllowed(model.to_string())),
+        }
+    }
+
+    pub fn allowed_models(&self) -> &[String] {
+        &self.allowed_models
+    }
+}
+
+/// Fastembed model named by its code, e.g. `Qdrant/Splade_PP_en_v1`.
+fn fastembed_model<T>(models: Vec<ModelInfo<T>>, code: &str) -> Result<T> {
+    models
+        .into_iter()
+        .find(|m| m.model_code.eq_ignore_ascii_case(code))
+        .map(|m| m.model)
+        .ok_or_else(|| Error::LlmError(format!("unknown fastembed model: {code}")))
+}
+
+fn build_sparse(model: &str, cache_dir: &Option<PathBuf>) -> Result<FastEmbed> {
+    let model = fastembed_model(SparseTextEmbedding::list_supported_models(), model)?;
+    let mut options = SparseInitOptions::new(model);
+    if let Some(dir) = cache_dir {
+        options = options.with_cache_dir(dir.clone());
+    }
+    let sparse_embedding = SparseTextEmbedding::try_new(options)
+        .map_err(|e| Error::LlmError(e.to_string()))?;
+
+    FastEmbed::builder()
+        .embedding_model(sparse_embedding)
+        .build()
+        .map_err(|e| Error::LlmError(e.to_string()))
+}
@@ -1,30 +1,93 @@
 use crate::{Error, Result};
 use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use sqlx::{Pool, Postgres};
 use std::time::{Duration, Instant};
-use async_openai::Client as OpenAIClient;
 
 use swiftide::{integrations::{
     qdrant::Qdrant,
     redis::Redis,
-    ollama::Ollama,
 }, query};
 use swiftide::indexing::EmbeddedField;
 use swiftide::indexing::transformers::{metadata_keywords, metadata_qa_text, metadata_summary, metadata_title};
-use swiftide::integrations::ollama::config::OllamaConfig;
 use swiftide::integrations::qdrant::{Distance, VectorConfig};
-use swiftide::query::{answers, query_transformers, response_transformers};
+use swiftide::query::{answers, query_transformers, response_transformers, states};
+use swiftide::template::Template;
+use swiftide::traits::{EmbeddingModel, SparseEmbeddingModel};
 use tracing::instrument;
 use crate::ctx::Ctx;
+use crate::metrics;
+use crate::model::llm::{LlmClient, LlmRegistry};
+use crate::model::pipeline::{PipelineConfig, PipelineParams, QueryStage, ResolvedPipeline, ResponseStage};
+use crate::model::prompt::PromptSet;
+use crate::model::agent::{self, AgentStep};
+use crate::model::answer_cache::AnswerCache;
+use crate::model::chat_events::{ChatHub, Progress};
+use crate::model::grounding;
+use crate::model::transformers::{RelevanceFilter, WhenDocuments};
+use crate::model::retrieval::{QdrantRetriever, RetrievalParams, RetrievalStrategy, RetrievedDocument};
+use crate::model::transformers::{ExpandQuery, HypotheticalAnswer, RewriteQuery, TemplatedAnswer, CHAT_ANSWER_PROMPT};
+use crate::model::stages::Timed;
 
 pub type Db = Pool<Postgres>;
 
+pub const QDRANT_COLLECTION: &str = "knowledge-base";
+
+/// Fields that also get a sparse vector when a sparse model is configured.
+/// `EmbedMode::Both` produces one for every field, so all of them are declared.
+fn sparse_fields() -> Vec<EmbeddedField> {
+    vec![
+        EmbeddedField::Combined,
+        EmbeddedField::Chunk,
+        EmbeddedField::Metadata(metadata_qa_text::NAME.into()),
+        EmbeddedField::Metadata(metadata_summary::NAME.into()),
+        EmbeddedField::Metadata(metadata_title::NAME.into()),
+        EmbeddedField::Metadata(metadata_keywords::NAME.into()),
+    ]
+}
+
+/// Answer together with the documents retrieved for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerWithSources {
+    pub answer: String,
+    pub documents: Vec<RetrievedDocument>,
+    /// Text of the retrieved chunks, in retrieval order.
+    pub contexts: Vec<String>,
+    /// Share of the answer's claims supported by the contexts, when the groundedness check ran.
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// `confidence` reached `SERVICE_GROUNDEDNESS_THRESHOLD`.
+    #[serde(default)]
+    pub grounded: Option<bool>,
+    /// No document passed retrieval, so `answer` is the no-answer message.
+    #[serde(default)]
+    pub fallback: bool,
+    /// Config the answer was produced with, kept with chat answers for feedback review.
+    #[serde(default)]
+    pub pipeline: Option<ResolvedPipeline>,
+    /// Tool calls of an agent-mode answer, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agent_steps: Vec<AgentStep>,
+}
+
+/// What a chat answer adds to a plain query.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChatTurn<'a> {
+    /// Summary and recent messages of the conversation, rendered into the answer prompt.
+    pub history: Option<&'a str>,
+    /// Clients following the conversation get the stages and answer tokens.
+    pub progress: Option<&'a Progress>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ModelManager {
     pub db: Db,
     pub qdrant: Qdrant,
     pub redis_cache: Redis,
-    pub ollama: Ollama,
+    pub answer_cache: Option<AnswerCache>,
+    pub llm: LlmRegistry,
+    pub chat_hub: ChatHub,
 }
 
 impl ModelManager {
@@ -38,12 +101,19 @@ impl ModelManager {
 
         let redis_cache = Redis::try_from_url(&config.REDIS_URL, "knowledge-base")
             .map_err(|e| Error::RedisError(e.to_string()))?;
+        let answer_cache = AnswerCache::connect(config).await;
 
-        let qdrant = Qdrant::try_from_url(&config.QDRANT_URL)
+        let llm = LlmRegistry::from_config(config)?;
+        for (name, preset) in &config.PIPELINE_PRESETS {
+            preset.validate(&llm).map_err(|e| Error::PipelineConfigInvalid(format!("preset {name}: {e}")))?;
+        }
+        let vector_size = llm.embed_dimensions().await;
+
+        let mut qdrant = Qdrant::try_from_url(&config.QDRANT_URL)
             .map_err(|e| Error::QdrantError(e.to_string()))?
             .batch_size(64)
-            .vector_size(1024)
-            .collection_name("knowledge-base")
+            .vector_size(vector_size as u64)
+            .collection_name(QDRANT_COLLECTION)
             .with_vector(EmbeddedField::Combined)
             .with_vector(EmbeddedField::Chunk)
             .with_vector(EmbeddedField::Metadata(metadata_qa_text::NAME.into()))
@@ -51,93 +121,378 @@ impl ModelManager {
             .with_vector(VectorConfig::builder()
                 .embedded_field(EmbeddedField::Metadata(metadata_title::NAME.into()))
                 .distance(Distance::Manhattan).build()?)
-            .with_vector(EmbeddedField::Metadata(metadata_keywords::NAME.into()))
-            .build().map_err(|e| Error::QdrantError(e.to_string()))?;
-
-        let mut cfg = OllamaConfig::default();
-        cfg.with_api_base("http://ollama:11434/v1");
-        let custom_client = OpenAIClient::with_config(cfg);
-
-        let ollama = Ollama::builder()
-            .client(custom_client)
-            .default_prompt_model("llama3.1:latest")
-            .default_embed_model("bge-m3:latest")
-            .build()?;
+            .with_vector(EmbeddedField::Metadata(metadata_keywords::NAME.into()));
+        if llm.sparse().is_some() {
+            for field in sparse_fields() {
+                qdrant = qdrant.with_sparse_vector(field);
+            }
+        }
+        let qdrant = qdrant.build().map_err(|e| Error::QdrantError(e.to_string()))?;
 
         Ok(Self {
             db,
             qdrant,
             redis_cache,
-            ollama,
+            answer_cache,
+            llm,
+            chat_hub: ChatHub::default(),
         })
     }
 
     #[instrument(skip_all, name = "ModelManager.query_data")]
-    pub async fn query_data(&self, ctx: &Ctx, prompt: &str) -> Result<Vec<String>> {
-        let pipeline = query::Pipeline::default()
-            .then_transform_query(query_transformers::GenerateSubquestions::from_client(
-                self.ollama.clone(),
-            ))
-            .then_transform_query(query_transformers::Embed::from_client(
-                self.ollama.clone(),
-            ))
-            .then_retrieve(self.qdrant.clone())
-            .then_transform_response(response_transformers::Summary::from_client(
-                self.ollama.clone(),
-            ))
-            .then_answer(answers::Simple::from_client(self.ollama.clone()));
+    pub async fn query_data(
+        &self,
+        ctx: &Ctx,
+        prompt: &str,
+        model: Option<&str>,
+        params: &PipelineParams,
+    ) -> Result<Vec<RetrievedDocument>> {
+        let result = self.run_query_pipeline(ctx, "query_data", prompt, ChatTurn::default(), model, params).await?;
+
+        Ok(result.documents)
+    }
+
+    #[instrument(skip_all, name = "ModelManager.fine_tune_prompt")]
+    pub async fn fine_tune_prompt(
+        &self,
+        ctx: &Ctx,
+        prompt: &str,
+        model: Option<&str>,
+        params: &PipelineParams,
+    ) -> Result<AnswerWithSources> {
+        self.run_query_pipeline(ctx, "fine_tune_prompt", prompt, ChatTurn::default(), model, params).await
+    }
+
+    /// Answers a chat prompt in the context of its conversation.
+    #[instrument(skip_all, name = "ModelManager.chat_answer")]
+    pub async fn chat_answer(
+        &self,
+        ctx: &Ctx,
+        prompt: &str,
+        turn: ChatTurn<'_>,
+        model: Option<&str>,
+        params: &PipelineParams,
+    ) -> Result<AnswerWithSources> {
+        self.run_query_pipeline(ctx, "chat", prompt, turn, model, params).await
+    }
+
+    /// Answers a chat prompt in agent mode, letting the model call tools
+    /// instead of running the retrieval pipeline once.
+    #[instrument(skip_all, name = "ModelManager.agent_answer")]
+    pub async fn agent_answer(
+        &self,
+        ctx: &Ctx,
+        prompt: &str,
+        turn: ChatTurn<'_>,
+        model: Option<&str>,
+        params: &PipelineParams,
+    ) -> Result<AnswerWithSources> {
+        let llm = self.llm.prompt_for(model)?;
+        let resolved = params.resolve()?;
+        resolved.config.validate(&self.llm)?;
+        let prompts = PromptSet::load(ctx, self).await?;
 
         let start = Instant::now();
-        let result = pipeline.query(prompt).await.map_err(|e| Error::QueryError(e.to_string()))?;
-        let duration_ms = start.elapsed().as_millis() as i32;
+        let result = agent::answer(ctx, self, &llm, prompt, turn, &resolved.config.retrieval, &prompts).await;
+        let elapsed = start.elapsed();
+        metrics::record_pipeline("chat_agent", llm.prompt_model(), elapsed);
+        self.insert_pipeline_log(ctx, "chat_agent", elapsed.as_millis() as i32, result.is_ok(), Some(&resolved)).await;
 
-        let _ = sqlx::query(
-            "INSERT INTO pipeline_log (user_id, pipeline, duration_ms) VALUES ($1, 'query_data', $2)"
-        )
-            .bind(ctx.user_id())
-            .bind(duration_ms)
-            .execute(&self.db)
-            .await;
+        let mut answer = result?;
+        answer.pipeline = Some(resolved);
+        Ok(answer)
+    }
+
+    #[instrument(skip_all, name = "ModelManager.answer_with_sources")]
+    pub async fn answer_with_sources(
+        &self,
+        ctx: &Ctx,
+        name: &'static str,
+        prompt: &str,
+        model: Option<&str>,
+        params: &PipelineParams,
+    ) -> Result<AnswerWithSources> {
+        self.run_query_pipeline(ctx, name, prompt, ChatTurn::default(), model, params).await
+    }
+
+    /// Retrieval (and reranking) only, without subquestions, summary or answer.
+    /// Cheap enough to run several strategies side by side.
+    #[instrument(skip_all, name = "ModelManager.retrieve")]
+    pub async fn retrieve(&self, prompt: &str, retrieval: &RetrievalParams) -> Result<Vec<RetrievedDocument>> {
+        let retriever = QdrantRetriever::new(self.qdrant.clone());
+        let documents = self.search_chunks(prompt, retrieval, retriever).await?;
+        Ok(documents.iter().map(RetrievedDocument::from).collect())
+    }
 
-        let documents = result.documents()
-            .iter()
-            .map(|doc| doc.metadata().get("Title").unwrap().to_string())
-            .collect();
+    /// Like `retrieve`, returning the chunks themselves.
+    pub async fn search_chunks(
+        &self,
+        prompt: &str,
+        retrieval: &RetrievalParams,
+        retriever: QdrantRetriever,
+    ) -> Result<Vec<query::Document>> {
+        let strategy = RetrievalStrategy::from_params(retrieval, &self.llm)?;
+
+        let embedding = match strategy.mode.uses_dense() {
+            true => self.llm.embed()
+                .embed(vec![prompt.to_string()])
+                .await
+                .map_err(|e| Error::LlmError(e.to_string()))?
+                .pop(),
+            false => None,
+        };
+        let sparse = match (strategy.mode.uses_sparse(), self.llm.sparse()) {
+            (true, Some(sparse)) => sparse
+                .sparse_embed(vec![prompt.to_string()])
+                .await
+                .map_err(|e| Error::LlmError(e.to_string()))?
+                .pop()
+                .map(|s| s.indices.into_iter().zip(s.values).collect()),
+            _ => None,
+        };
+
+        let mut documents = retriever
+            .search_documents(&strategy, embedding, sparse)
+            .await
+            .map_err(|e| Error::QueryError(e.to_string()))?;
+        if let (Some(top_n), Some(reranker)) = (strategy.rerank_top_n, self.llm.reranker()) {
+            documents = reranker
+                .with_top_n(top_n)
+                .rerank_documents(prompt, &documents)
+                .await
+                .map_err(|e| Error::QueryError(e.to_string()))?;
+        }
+        if let Some(min_score) = strategy.min_score {
+            RelevanceFilter::new(min_score).retain(&mut documents);
+        }
         Ok(documents)
     }
 
-    #[instrument(skip_all, name = "ModelManager.fine_tune_prompt")]
-    pub async fn fine_tune_prompt(
+    /// Runs the configured pipeline, answering from the answer cache when the
+    /// same (or, with similarity lookup, a near-duplicate) question was
+    /// answered before with the same config and corpus.
+    async fn run_query_pipeline(
         &self,
         ctx: &Ctx,
+        name: &'static str,
         prompt: &str,
-    ) -> Result<String> {
-        let pipeline = query::Pipeline::default()
-            .then_transform_query(query_transformers::GenerateSubquestions::from_client(
-                self.ollama.clone(),
-            ))
-            .then_transform_query(query_transformers::Embed::from_client(
-                self.ollama.clone(),
-            ))
-            .then_retrieve(self.qdrant.clone())
-            .then_transform_response(response_transformers::Summary::from_client(
-                self.ollama.clone(),
-            ))
-            .then_answer(answers::Simple::from_client(self.ollama.clone()));
+        turn: ChatTurn<'_>,
+        model: Option<&str>,
+        params: &PipelineParams,
+    ) -> Result<AnswerWithSources> {
+        let llm = self.llm.prompt_for(model)?;
+        let resolved = params.resolve()?;
+        let strategy = resolved.config.validate(&self.llm)?;
+        let prompts = PromptSet::load(ctx, self).await?;
+
+        // answers that depend on the conversation so far are not reusable
+        let cache = self.answer_cache.as_ref().filter(|_| params.cache.unwrap_or(true) && turn.history.is_none());
+        let mut cache_key = match cache {
+            Some(cache) => cache.key(ctx, name, llm.prompt_model(), prompt, &resolved.config, &prompts).await,
+            None => None,
+        };
+        if let (Some(cache), Some(key)) = (cache, cache_key.as_mut()) {
+            let embed = async {
+                self.llm.embed().embed(vec![prompt.to_string()]).await.ok()?.pop()
+            };
+            match cache.lookup(key, embed).await {
+                Some((mut answer, kind)) => {
+                    metrics::record_answer_cache_hit(name, kind);
+                    // the key ignores the preset name, so report the one asked for
+                    answer.pipeline = Some(resolved);
+                    return Ok(answer);
+                }
+                None => metrics::record_answer_cache_miss(name),
+            }
+        }
+
+        let pipeline = self.build_query_pipeline(name, &llm, &resolved.config, &prompts, strategy, turn)?;
 
         let start = Instant::now();
-        let result = pipeline.query(prompt).await.map_err(|e| Error::QueryError(e.to_string()))?;
-        let duration_ms = start.elapsed().as_millis() as i32;
+        let result = pipeline.query(prompt).await;
+        let elapsed = start.elapsed();
+        metrics::record_pipeline(name, llm.prompt_model(), elapsed);
+        self.insert_pipeline_log(ctx, name, elapsed.as_millis() as i32, result.is_ok(), Some(&resolved)).await;
 
+        let result = result.map_err(|e| Error::QueryError(e.to_string()))?;
+        let mut answer = AnswerWithSources {
+            answer: result.answer().to_string(),
+            documents: result.documents().iter().map(RetrievedDocument::from).collect(),
+            contexts: result.documents().iter().map(|d| d.content().to_string()).collect(),
+            confidence: None,
+            grounded: None,
+            fallback: result.documents().is_empty(),
+            pipeline: Some(resolved.clone()),
+            agent_steps: Vec::new(),
+        };
+        if resolved.config.checks_groundedness() && !answer.fallback {
+            if let Some(progress) = turn.progress {
+                progress.stage("groundedness");
+            }
+            let template = prompts.for_stage("groundedness", &resolved.config.prompts)?;
+            let start = Instant::now();
+            let score = grounding::groundedness(&llm, template.as_deref(), &answer.contexts, &answer.answer).await;
+            metrics::record_stage(name, llm.prompt_model(), "groundedness", start.elapsed(), score.is_some());
+            answer.confidence = score;
+            answer.grounded = score.map(|s| s >= crate::config::config().GROUNDEDNESS_THRESHOLD);
+        }
+        if let (Some(cache), Some(key)) = (cache, &cache_key) {
+            cache.put(key, &answer).await;
+        }
+        Ok(answer)
+    }
+
+    /// Invalidates cached answers after documents were added or removed.
+    pub async fn corpus_changed(&self) {
+        if let Some(cache) = &self.answer_cache {
+            cache.bump_corpus_version().await;
+        }
+    }
+
+    fn build_query_pipeline(
+        &self,
+        name: &'static str,
+        llm: &LlmClient,
+        config: &PipelineConfig,
+        prompts: &PromptSet,
+        strategy: RetrievalStrategy,
+        turn: ChatTurn<'_>,
+    ) -> Result<query::Pipeline<'static, RetrievalStrategy, states::Answered>> {
+        let template = |stage: &str| prompts.for_stage(stage, &config.prompts);
+        let embed = self.llm.embed();
+        let mode = strategy.mode;
+        let rerank_top_n = strategy.rerank_top_n;
+        let min_score = strategy.min_score;
+        let no_answer = crate::config::config().NO_ANSWER_MESSAGE.clone();
+        let progress = turn.progress.cloned();
+        let mut pipeline = query::Pipeline::from_search_strategy(strategy);
+        for stage in &config.query_transformers {
+            pipeline = match stage {
+                QueryStage::Subquestions => {
+                    let transformer = match template(stage.name())? {
+                        Some(t) => query_transformers::GenerateSubquestions::builder()
+                            .client(llm.clone())
+                            .prompt_template(Template::from(t))
+                            .build()
+                            .map_err(|e| Error::SwiftideError(e.to_string()))?,
+                        None => query_transformers::GenerateSubquestions::from_client(llm.clone()),
+                    };
+                    pipeline.then_transform_query(Timed::new(transformer, name, llm.prompt_model(), stage.name())
+                        .with_progress(progress.clone()))
+                }
+                QueryStage::Rewrite => pipeline.then_transform_query(Timed::new(
+                    RewriteQuery::new(llm.clone()).with_template(template(stage.name())?),
+                    name, llm.prompt_model(), stage.name(),
+                ).with_progress(progress.clone())),
+                QueryStage::Hyde => pipeline.then_transform_query(Timed::new(
+                    HypotheticalAnswer::new(llm.clone()).with_template(template(stage.name())?),
+                    name, llm.prompt_model(), stage.name(),
+                ).with_progress(progress.clone())),
+                QueryStage::MultiQuery => pipeline.then_transform_query(Timed::new(
+                    ExpandQuery::new(llm.clone(), config.query_variants()).with_template(template(stage.name())?),
+                    name, llm.prompt_model(), stage.name(),
+                ).with_progress(progress.clone())),
+            };
+        }
+
+        // multi-query embeds each variant inside the retriever
+        let multi_query = config.query_transformers.contains(&QueryStage::MultiQuery);
+        let mut retriever = QdrantRetriever::new(self.qdrant.clone());
+        if multi_query {
+            retriever = retriever.with_variants(embed.clone(), self.llm.sparse().cloned());
+        }
+        if mode.uses_dense() && !multi_query {
+            pipeline = pipeline.then_transform_query(Timed::new(
+                query_transformers::Embed::from_client(embed.clone()),
+                name, embed.embed_model(), "embed",
+            ).with_progress(progress.clone()));
+        }
+        if let (true, false, Some(sparse)) = (mode.uses_sparse(), multi_query, self.llm.sparse()) {
+            pipeline = pipeline.then_transform_query(Timed::new(
+                query_transformers::SparseEmbed::from_client(sparse.clone()),
+                name, self.llm.sparse_model().unwrap_or_default(), "sparse_embed",
+            ).with_progress(progress.clone()));
+        }
+
+        let mut pipeline = pipeline.then_retrieve(Timed::new(
+            retriever,
+            name, embed.embed_model(), "retrieve",
+        ).with_progress(progress.clone()));
+        if let (Some(top_n), Some(reranker)) = (rerank_top_n, self.llm.reranker()) {
+            pipeline = pipeline.then_transform_response(Timed::new(
+                reranker.with_top_n(top_n),
+                name, reranker.model_name(), "rerank",
+            ).with_progress(progress.clone()));
+        }
+        if let Some(min_score) = min_score {
+            pipeline = pipeline.then_transform_response(RelevanceFilter::new(min_score));
+        }
+
+        // summary and answer only run when some document is left
+        if config.response == ResponseStage::Summary {
+            let summary = match template("summary")? {
+                Some(t) => response_transformers::Summary::builder()
+                    .client(llm.clone())
+                    .prompt_template(Template::from(t))
+                    .build()
+                    .map_err(|e| Error::SwiftideError(e.to_string()))?,
+                None => response_transformers::Summary::from_client(llm.clone()),
+            };
+            pipeline = pipeline.then_transform_response(Timed::new(
+                WhenDocuments::new(summary),
+                name, llm.prompt_model(), "summary",
+            ).with_progress(progress.clone()));
+        }
+
+        // an inline template wins over a stored one, and the Swiftide default
+        // has no place for the conversation history and cannot stream
+        let answer_template = match &config.answer_template {
+            Some(t) => Some(t.clone()),
+            None => template("answer")?.or_else(|| {
+                (turn.history.is_some() || turn.progress.is_some()).then(|| CHAT_ANSWER_PROMPT.to_string())
+            }),
+        };
+        Ok(match answer_template {
+            Some(template) => pipeline.then_answer(Timed::new(
+                WhenDocuments::new(
+                    TemplatedAnswer::new(llm.clone(), template, config.response == ResponseStage::Summary)
+                        .with_history(turn.history.map(str::to_string))
+                        .with_progress(progress.clone()),
+                ).with_fallback(no_answer),
+                name, llm.prompt_model(), "answer",
+            ).with_progress(progress.clone())),
+            None => pipeline.then_answer(Timed::new(
+                WhenDocuments::new(answers::Simple::from_client(llm.clone())).with_fallback(no_answer),
+                name, llm.prompt_model(), "answer",
+            ).with_progress(progress.clone())),
+        })
+    }
+
+    /// Best-effort insert into `pipeline_log`; a failed insert never fails the pipeline.
+    pub async fn log_pipeline_run(&self, ctx: &Ctx, pipeline: &str, duration_ms: i32, ok: bool) {
+        self.insert_pipeline_log(ctx, pipeline, duration_ms, ok, None).await
+    }
+
+    async fn insert_pipeline_log(
+        &self,
+        ctx: &Ctx,
+        pipeline: &str,
+        duration_ms: i32,
+        ok: bool,
+        resolved: Option<&ResolvedPipeline>,
+    ) {
         let _ = sqlx::query(
-            "INSERT INTO pipeline_log (user_id, pipeline, duration_ms) VALUES ($1, 'fine_tune_prompt', $2)"
+            "INSERT INTO pipeline_log (user_id, pipeline, duration_ms, status, preset, config) \
+             VALUES ($1, $2, $3, $4, $5, $6)"
         )
             .bind(ctx.user_id())
+            .bind(pipeline)
             .bind(duration_ms)
+            .bind(if ok { "ok" } else { "error" })
+            .bind(resolved.and_then(|r| r.preset.as_deref()))
+            .bind(resolved.map(|r| Json(&r.config)))
             .execute(&self.db)
             .await;
-
-        Ok(result.answer().to_string())
     }
 
     pub fn db(&self) -> &Db {
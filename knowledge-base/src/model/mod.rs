@@ -5,5 +5,20 @@ pub mod manager;
 pub mod base;
 pub mod user;
 pub mod chat;
-
-
+pub mod stages;
+pub mod llm;
+pub mod retrieval;
+pub mod rerank;
+pub mod eval;
+pub mod pipeline;
+pub mod transformers;
+pub mod prompt;
+pub mod answer_cache;
+pub mod grounding;
+pub mod feedback;
+pub mod chat_memory;
+pub mod chat_export;
+pub mod chat_share;
+pub mod chat_events;
+pub mod agent;
+pub mod action_items;
@@ -0,0 +1,200 @@
+//! Query pipeline configuration: which stages run, selected per request either
+//! by preset name or inline.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::config;
+use crate::model::llm::LlmRegistry;
+use crate::model::prompt::PROMPT_STAGES;
+use crate::model::retrieval::{RetrievalParams, RetrievalStrategy};
+use crate::{Error, Result};
+
+/// Preset used when a request names none and `SERVICE_DEFAULT_PIPELINE` is unset.
+pub const DEFAULT_PRESET: &str = "default";
+
+/// Upper bound for `query_variants`; every variant costs one search per vector.
+const MAX_QUERY_VARIANTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryStage {
+    /// Swiftide `GenerateSubquestions`.
+    Subquestions,
+    /// Rewrites the question into a self-contained search query.
+    Rewrite,
+    /// HyDE: searches with a generated hypothetical answer instead of the question.
+    Hyde,
+    /// Searches the question and generated rephrasings, merging the results.
+    MultiQuery,
+}
+
+impl QueryStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Subquestions => "subquestions",
+            Self::Rewrite => "rewrite",
+            Self::Hyde => "hyde",
+            Self::MultiQuery => "multi_query",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseStage {
+    /// Summarize the retrieved documents before answering.
+    #[default]
+    Summary,
+    /// Answer from the retrieved documents as they are.
+    Raw,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    pub query_transformers: Vec<QueryStage>,
+    /// `mode`, `top_k`, `vectors`, `rerank`, ... as accepted per request.
+    #[serde(flatten)]
+    pub retrieval: RetrievalParams,
+    /// Rephrasings generated by `multi_query`; `SERVICE_MULTI_QUERY_VARIANTS` when absent.
+    pub query_variants: Option<usize>,
+    pub response: ResponseStage,
+    /// Verify the answer against the contexts; `SERVICE_GROUNDEDNESS_CHECK` when absent.
+    pub groundedness_check: Option<bool>,
+    /// Answer prompt with `{{ question }}` and `{{ context }}`; Swiftide's default when absent.
+    pub answer_template: Option<String>,
+    /// Stored prompt template per stage, e.g. `{"answer": "answer_support"}`.
+    /// Stages not listed use the template stored under their own name, if any.
+    pub prompts: HashMap<String, String>,
+    /// Fields nobody reads, rejected by `validate`. `deny_unknown_fields`
+    /// does not work together with the flattened `retrieval`, and without
+    /// this a misspelled field would silently run the defaults.
+    #[serde(flatten)]
+    pub unknown: HashMap<String, serde_json::Value>,
+}
+
+impl Default for PipelineConfig {
+    /// The pipeline `query_data` and `fine_tune_prompt` always ran.
+    fn default() -> Self {
+        Self {
+            query_transformers: vec![QueryStage::Subquestions],
+            retrieval: RetrievalParams::default(),
+            query_variants: None,
+            response: ResponseStage::Summary,
+            groundedness_check: None,
+            answer_template: None,
+            prompts: HashMap::new(),
+            unknown: HashMap::new(),
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Checks the config against the configured models and returns its retrieval strategy.
+    pub fn validate(&self, llm: &LlmRegistry) -> Result<RetrievalStrategy> {
+        self.check_fields()?;
+        let mut seen = Vec::with_capacity(self.query_transformers.len());
+        for stage in &self.query_transformers {
+            if seen.contains(stage) {
+                return Err(Error::PipelineConfigInvalid(format!("duplicate query transformer {}", stage.name())));
+            }
+            seen.push(*stage);
+        }
+        // later stages would rewrite the joined variants as if they were one question
+        if seen.contains(&QueryStage::MultiQuery) && seen.last() != Some(&QueryStage::MultiQuery) {
+            return Err(Error::PipelineConfigInvalid("multi_query must be the last query transformer".to_string()));
+        }
+        if !(1..=MAX_QUERY_VARIANTS).contains(&self.query_variants()) {
+            return Err(Error::PipelineConfigInvalid(format!("query_variants must be between 1 and {MAX_QUERY_VARIANTS}")));
+        }
+        if let Some(stage) = self.prompts.keys().find(|k| !PROMPT_STAGES.iter().any(|(name, _)| name == k)) {
+            return Err(Error::PipelineConfigInvalid(format!("unknown prompt stage {stage}")));
+        }
+        if let Some(template) = &self.answer_template {
+            if !template.contains("question") {
+                return Err(Error::PipelineConfigInvalid("answer_template must use {{ question }}".to_string()));
+            }
+        }
+        RetrievalStrategy::from_params(&self.retrieval, llm)
+    }
+
+    fn check_fields(&self) -> Result<()> {
+        let mut unknown: Vec<&str> = self.unknown.keys().map(String::as_str).collect();
+        unknown.sort();
+        match unknown.is_empty() {
+            true => Ok(()),
+            false => Err(Error::PipelineConfigInvalid(format!("unknown pipeline fields: {}", unknown.join(", ")))),
+        }
+    }
+
+    pub fn checks_groundedness(&self) -> bool {
+        self.groundedness_check.unwrap_or(config().GROUNDEDNESS_CHECK)
+    }
+
+    pub fn query_variants(&self) -> usize {
+        self.query_variants.unwrap_or(config().MULTI_QUERY_VARIANTS)
+    }
+}
+
+/// Inline config or preset name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PipelineSelection {
+    Preset(String),
+    Config(Box<PipelineConfig>),
+}
+
+/// Pipeline part of a request body: `pipeline` plus retrieval overrides applied on top of it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PipelineParams {
+    pub pipeline: Option<PipelineSelection>,
+    #[serde(flatten)]
+    pub retrieval: RetrievalParams,
+    /// `false` skips the answer cache for this request.
+    pub cache: Option<bool>,
+}
+
+/// Config a query actually ran with, as recorded in `pipeline_log`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedPipeline {
+    pub preset: Option<String>,
+    pub config: PipelineConfig,
+}
+
+impl PipelineParams {
+    pub fn resolve(&self) -> Result<ResolvedPipeline> {
+        let (preset, mut config) = match &self.pipeline {
+            Some(PipelineSelection::Config(config)) => (None, (**config).clone()),
+            Some(PipelineSelection::Preset(name)) => (Some(name.clone()), preset(name)?),
+            None => {
+                let name = config().DEFAULT_PIPELINE.clone();
+                let preset = preset(&name)?;
+                (Some(name), preset)
+            }
+        };
+        config.check_fields()?;
+        config.retrieval.apply(&self.retrieval);
+        Ok(ResolvedPipeline { preset, config })
+    }
+}
+
+fn preset(name: &str) -> Result<PipelineConfig> {
+    match config().PIPELINE_PRESETS.get(name) {
+        Some(config) => Ok(config.clone()),
+        None if name == DEFAULT_PRESET => Ok(PipelineConfig::default()),
+        None => Err(Error::PipelinePresetNotFound(name.to_string())),
+    }
+}
+
+/// Parses `SERVICE_PIPELINE_PRESETS`, a JSON object of preset name to config.
+pub fn parse_presets(json: &str) -> std::result::Result<HashMap<String, PipelineConfig>, serde_json::Error> {
+    use serde::de::Error as _;
+
+    let presets: HashMap<String, PipelineConfig> = serde_json::from_str(json)?;
+    for (name, preset) in &presets {
+        preset.check_fields().map_err(|e| serde_json::Error::custom(format!("preset {name}: {e:?}")))?;
+    }
+    Ok(presets)
+}
@@ -0,0 +1,324 @@
+//! src/model/prompt.rs
+//! versioned prompt templates that replace the built-in prompts by name
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use lazy_regex::regex;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use swiftide::prompt::Prompt;
+
+use crate::model::manager::ModelManager;
+use crate::{Ctx, Error, Result};
+
+/// Prompts that can be replaced, named after the stage that uses them.
+/// Stages marked `swiftide` render with the variables of the Swiftide
+/// default prompt they replace.
+pub const PROMPT_STAGES: &[(&str, &str)] = &[
+    ("metadata_qa_text", "indexing: questions a chunk answers (swiftide)"),
+    ("metadata_summary", "indexing: chunk summary (swiftide)"),
+    ("metadata_title", "indexing: chunk title (swiftide)"),
+    ("metadata_keywords", "indexing: chunk keywords (swiftide)"),
+    ("subquestions", "query: subquestion generation (swiftide)"),
+    ("rewrite", "query: search query rewrite, {{ question }}"),
+    ("hyde", "query: hypothetical answer, {{ question }}"),
+    ("multi_query", "query: rephrasings, {{ question }} and {{ num_variants }}"),
+    ("summary", "response: summary of the retrieved documents (swiftide)"),
+    ("answer", "answer: {{ question }}, {{ context }} and, in chats, {{ history }}"),
+    ("chat_title", "chat: conversation title, {{ question }} and {{ answer }}"),
+    ("chat_summary", "chat: rolling summary of trimmed history, {{ summary }} and {{ messages }}"),
+    ("agent", "chat: system prompt of agent-mode answers, no variables"),
+    ("action_items", "tasks: action item extraction, {{ text }} and {{ reference }}"),
+    ("groundedness", "answer check: {{ context }} and {{ answer }}, ends with `SCORE: <0..1>`"),
+];
+
+/// Our own stages render `question`; a template without it ignores the user.
+const STAGES_REQUIRING_QUESTION: &[&str] = &["rewrite", "hyde", "multi_query", "answer"];
+
+/// Used as is, never rendered.
+const UNRENDERED_STAGES: &[&str] = &["agent"];
+
+/// Variables our own stages render with. New versions of these stages are
+/// rendered once with placeholders before they are stored.
+const STAGE_VARIABLES: &[(&str, &[&str])] = &[
+    ("rewrite", &["question"]),
+    ("hyde", &["question"]),
+    ("multi_query", &["question", "num_variants"]),
+    ("answer", &["question", "context", "history"]),
+    ("chat_title", &["question", "answer"]),
+    ("chat_summary", &["summary", "messages"]),
+    ("action_items", &["text", "reference"]),
+    ("groundedness", &["context", "answer"]),
+];
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Data structures                                                          */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct PromptTemplate {
+    pub id: i64,
+    pub name: String,
+    pub version: i32,
+    pub template: String,
+    /// Variables the template renders, e.g. `question`.
+    pub variables: Vec<String>,
+    pub description: Option<String>,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptTemplateForCreate {
+    pub template: String,
+    pub description: Option<String>,
+}
+
+/// Latest version of every stored template, loaded once per pipeline run.
+#[derive(Debug, Clone, Default)]
+pub struct PromptSet {
+    templates: HashMap<String, String>,
+}
+
+impl PromptSet {
+    pub async fn load(ctx: &Ctx, mm: &ModelManager) -> Result<Self> {
+        let templates = PromptBmc::list_latest(ctx, mm)
+            .await?
+            .into_iter()
+            .map(|t| (t.name, t.template))
+            .collect();
+        Ok(Self { templates })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(String::as_str)
+    }
+
+    /// Changes whenever any stored template does, for cache keys.
+    pub fn fingerprint(&self) -> String {
+        let mut templates: Vec<_> = self.templates.iter().collect();
+        templates.sort();
+        serde_json::to_string(&templates).unwrap_or_default()
+    }
+
+    /// Template for `stage`: the one `overrides` names for it, else the one
+    /// stored under the stage's own name. `None` keeps the built-in prompt.
+    pub fn for_stage(&self, stage: &str, overrides: &HashMap<String, String>) -> Result<Option<String>> {
+        match overrides.get(stage) {
+            Some(name) => self
+                .get(name)
+                .map(|t| Some(t.to_string()))
+                .ok_or_else(|| Error::PromptTemplateNotFound(name.clone())),
+            None => Ok(self.get(stage).map(str::to_string)),
+        }
+    }
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  BMC                                                                      */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub struct PromptBmc;
+
+impl PromptBmc {
+    /// Stores `template` as the next version of `name`, which makes it the active one.
+    pub async fn create_version(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        name: &str,
+        template_c: PromptTemplateForCreate,
+    ) -> Result<PromptTemplate> {
+        validate_name(name)?;
+        let variables = validate_template(name, &template_c.template).await?;
+
+        // concurrent saves of the same name would pick the same next version
+        let mut tx = mm.db().begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO prompt_template (name, version, template, variables, description, created_by) \
+             SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4, $5 FROM prompt_template WHERE name = $1 \
+             RETURNING id"
+        )
+            .bind(name)
+            .bind(template_c.template)
+            .bind(variables)
+            .bind(template_c.description)
+            .bind(ctx.user_id())
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Self::get_by_id(ctx, mm, id).await
+    }
+
+    /// Copies an old version as the newest one.
+    pub async fn restore_version(ctx: &Ctx, mm: &ModelManager, name: &str, version: i32) -> Result<PromptTemplate> {
+        let old = Self::get_version(ctx, mm, name, version).await?;
+        let template_c = PromptTemplateForCreate {
+            template: old.template,
+            description: old.description,
+        };
+        Self::create_version(ctx, mm, name, template_c).await
+    }
+
+    async fn get_by_id(_ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<PromptTemplate> {
+        sqlx::query_as::<_, PromptTemplate>(
+            "SELECT id, name, version, template, variables, description, created_by, created_at \
+             FROM prompt_template WHERE id = $1"
+        )
+            .bind(id)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or(Error::EntityNotFound { entity: "prompt_template", id })
+    }
+
+    pub async fn get_latest(_ctx: &Ctx, mm: &ModelManager, name: &str) -> Result<PromptTemplate> {
+        sqlx::query_as::<_, PromptTemplate>(
+            "SELECT id, name, version, template, variables, description, created_by, created_at \
+             FROM prompt_template WHERE name = $1 ORDER BY version DESC LIMIT 1"
+        )
+            .bind(name)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or_else(|| Error::PromptTemplateNotFound(name.to_string()))
+    }
+
+    pub async fn get_version(_ctx: &Ctx, mm: &ModelManager, name: &str, version: i32) -> Result<PromptTemplate> {
+        sqlx::query_as::<_, PromptTemplate>(
+            "SELECT id, name, version, template, variables, description, created_by, created_at \
+             FROM prompt_template WHERE name = $1 AND version = $2"
+        )
+            .bind(name)
+            .bind(version)
+            .fetch_optional(mm.db())
+            .await?
+            .ok_or_else(|| Error::PromptTemplateNotFound(format!("{name} v{version}")))
+    }
+
+    /// Active (latest) version of every template.
+    pub async fn list_latest(_ctx: &Ctx, mm: &ModelManager) -> Result<Vec<PromptTemplate>> {
+        let templates = sqlx::query_as::<_, PromptTemplate>(
+            "SELECT DISTINCT ON (name) id, name, version, template, variables, description, created_by, created_at \
+             FROM prompt_template ORDER BY name, version DESC"
+        )
+            .fetch_all(mm.db())
+            .await?;
+        Ok(templates)
+    }
+
+    /// All versions of one template, newest first.
+    pub async fn list_versions(_ctx: &Ctx, mm: &ModelManager, name: &str) -> Result<Vec<PromptTemplate>> {
+        let templates = sqlx::query_as::<_, PromptTemplate>(
+            "SELECT id, name, version, template, variables, description, created_by, created_at \
+             FROM prompt_template WHERE name = $1 ORDER BY version DESC"
+        )
+            .bind(name)
+            .fetch_all(mm.db())
+            .await?;
+        if templates.is_empty() {
+            return Err(Error::PromptTemplateNotFound(name.to_string()));
+        }
+        Ok(templates)
+    }
+
+    /// Drops every version, so the stage falls back to its built-in prompt.
+    pub async fn delete(_ctx: &Ctx, mm: &ModelManager, name: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM prompt_template WHERE name = $1")
+            .bind(name)
+            .execute(mm.db())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::PromptTemplateNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/* ────────────────────────────────────────────────────────────────────────── */
+/*  Rendering                                                                */
+/* ────────────────────────────────────────────────────────────────────────── */
+
+pub fn to_prompt(template: &str, variables: &HashMap<String, serde_json::Value>) -> Prompt {
+    let mut prompt = Prompt::from(template.to_string());
+    for (key, value) in variables {
+        prompt = prompt.with_context_value(key.as_str(), value.clone());
+    }
+    prompt
+}
+
+/// Renders `template` with `variables`, failing on syntax errors and missing variables.
+pub async fn render(template: &str, variables: &HashMap<String, serde_json::Value>) -> Result<String> {
+    to_prompt(template, variables)
+        .render()
+        .await
+        .map_err(|e| Error::PromptTemplateInvalid(format!("{e:#}")))
+}
+
+/// Top-level variables used in `{{ ... }}` expressions, e.g. `node` for `{{ node.chunk }}`.
+pub fn template_variables(template: &str) -> Vec<String> {
+    regex!(r"\{\{-?\s*([A-Za-z_][A-Za-z0-9_]*)")
+        .captures_iter(template)
+        .map(|c| c[1].to_string())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    // taken by the admin routes next to `/prompts/:name`
+    if matches!(name, "stages" | "preview") {
+        return Err(Error::PromptTemplateInvalid(format!("template name {name:?} is reserved")));
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(Error::PromptTemplateInvalid(format!("invalid template name {name:?}")));
+    }
+    Ok(())
+}
+
+/// Rejects templates that would fail once active: the latest version is used
+/// by every pipeline and ingestion right away.
+async fn validate_template(name: &str, template: &str) -> Result<Vec<String>> {
+    if template.trim().is_empty() {
+        return Err(Error::PromptTemplateInvalid("template is empty".to_string()));
+    }
+    if UNRENDERED_STAGES.contains(&name) {
+        return Ok(Vec::new());
+    }
+    tera::Tera::default()
+        .add_raw_template(name, template)
+        .map_err(|e| Error::PromptTemplateInvalid(error_chain(&e)))?;
+    let variables = template_variables(template);
+    if STAGES_REQUIRING_QUESTION.contains(&name) && !variables.iter().any(|v| v == "question") {
+        return Err(Error::PromptTemplateInvalid(format!("{name} templates must use {{{{ question }}}}")));
+    }
+    if let Some((_, stage_variables)) = STAGE_VARIABLES.iter().find(|(stage, _)| *stage == name) {
+        let placeholders = stage_variables
+            .iter()
+            .map(|v| {
+                let value = match *v {
+                    "num_variants" => serde_json::json!(3),
+                    _ => serde_json::json!(format!("<{v}>")),
+                };
+                (v.to_string(), value)
+            })
+            .collect();
+        render(template, &placeholders).await?;
+    }
+    Ok(variables)
+}
+
+/// Tera puts the position and cause of a syntax error in the error's sources.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
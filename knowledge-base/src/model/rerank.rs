@@ -0,0 +1,102 @@
+//! Cross-encoder reranking of retrieved documents with a local fastembed model.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fastembed::{RerankInitOptions, TextRerank};
+use swiftide::query::{states, Document, Query};
+use swiftide::traits::TransformResponse;
+use tokio::task::spawn_blocking;
+
+use crate::{Error, Result};
+
+/// Reorders the retrieved candidates by cross-encoder score and keeps the best
+/// `top_n`, recording `rerank_score` next to the retrieval `score`.
+#[derive(Clone)]
+pub struct Reranker {
+    model: Arc<TextRerank>,
+    model_name: String,
+    top_n: usize,
+}
+
+impl fmt::Debug for Reranker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reranker")
+            .field("model_name", &self.model_name)
+            .field("top_n", &self.top_n)
+            .finish()
+    }
+}
+
+impl Reranker {
+    pub fn try_new(model_name: &str, cache_dir: Option<PathBuf>, top_n: usize) -> Result<Self> {
+        let model = TextRerank::list_supported_models()
+            .into_iter()
+            .find(|m| m.model_code.eq_ignore_ascii_case(model_name))
+            .map(|m| m.model)
+            .ok_or_else(|| Error::LlmError(format!("unknown reranker model: {model_name}")))?;
+        let mut options = RerankInitOptions::new(model);
+        if let Some(dir) = cache_dir {
+            options = options.with_cache_dir(dir);
+        }
+        let model = TextRerank::try_new(options).map_err(|e| Error::LlmError(e.to_string()))?;
+
+        Ok(Self {
+            model: Arc::new(model),
+            model_name: model_name.to_string(),
+            top_n,
+        })
+    }
+
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    pub fn with_top_n(&self, top_n: usize) -> Self {
+        Self {
+            top_n,
+            ..self.clone()
+        }
+    }
+
+    pub async fn rerank_documents(
+        &self,
+        question: &str,
+        documents: &[Document],
+    ) -> anyhow::Result<Vec<Document>> {
+        let question = question.to_string();
+        let contents: Vec<String> = documents.iter().map(|d| d.content().to_string()).collect();
+        let model = self.model.clone();
+        // ONNX inference is CPU bound; keep it off the async workers.
+        let ranked = spawn_blocking(move || model.rerank(question, contents, false, None)).await??;
+
+        Ok(ranked
+            .into_iter()
+            .take(self.top_n)
+            .filter_map(|result| {
+                let document = documents.get(result.index)?;
+                let mut metadata = document.metadata().clone();
+                metadata.insert("rerank_score", result.score);
+                Some(Document::new(document.content(), Some(metadata)))
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TransformResponse for Reranker {
+    async fn transform_response(
+        &self,
+        mut query: Query<states::Retrieved>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        if query.documents().is_empty() {
+            return Ok(query);
+        }
+
+        let reranked = self.rerank_documents(query.original(), query.documents()).await?;
+        *query.documents_mut() = reranked;
+        Ok(query)
+    }
+}
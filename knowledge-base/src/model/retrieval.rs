@@ -0,0 +1,517 @@
+//! Qdrant retrieval over dense and sparse vectors, merged with weighted
+//! reciprocal rank fusion.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use qdrant_client::qdrant::{Condition, Filter, QueryPointsBuilder, ScoredPoint};
+use serde::{Deserialize, Serialize};
+use strum_macros::AsRefStr;
+use swiftide::indexing::transformers::{metadata_keywords, metadata_qa_text, metadata_summary, metadata_title};
+use swiftide::indexing::{EmbeddedField, Metadata};
+use swiftide::integrations::fastembed::FastEmbed;
+use swiftide::integrations::qdrant::Qdrant;
+use swiftide::query::{states, Document, Query};
+use swiftide::traits::{EmbeddingModel, Retrieve, SearchStrategy, SparseEmbeddingModel};
+
+use crate::config::config;
+use crate::model::llm::{LlmClient, LlmRegistry};
+use crate::model::manager::QDRANT_COLLECTION;
+use crate::model::transformers::query_variants;
+use crate::{Error, Result};
+
+/// Rank constant from the original RRF paper; dampens the gap between top ranks.
+const RRF_K: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetrievalMode {
+    Dense,
+    Sparse,
+    Hybrid,
+}
+
+impl RetrievalMode {
+    pub fn uses_dense(self) -> bool {
+        matches!(self, Self::Dense | Self::Hybrid)
+    }
+
+    pub fn uses_sparse(self) -> bool {
+        matches!(self, Self::Sparse | Self::Hybrid)
+    }
+}
+
+impl FromStr for RetrievalMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "dense" => Ok(Self::Dense),
+            "sparse" => Ok(Self::Sparse),
+            "hybrid" => Ok(Self::Hybrid),
+            _ => Err(Error::ConfigWrongFormat("SERVICE_RETRIEVAL_MODE")),
+        }
+    }
+}
+
+/// Named vectors stored for every chunk (see `ModelManager::new`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, AsRefStr)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum NamedVector {
+    /// Chunk and metadata embedded together.
+    Combined,
+    Chunk,
+    /// Generated questions the chunk answers.
+    Questions,
+    Summary,
+    Title,
+    Keywords,
+}
+
+impl NamedVector {
+    pub fn embedded_field(self) -> EmbeddedField {
+        match self {
+            Self::Combined => EmbeddedField::Combined,
+            Self::Chunk => EmbeddedField::Chunk,
+            Self::Questions => EmbeddedField::Metadata(metadata_qa_text::NAME.into()),
+            Self::Summary => EmbeddedField::Metadata(metadata_summary::NAME.into()),
+            Self::Title => EmbeddedField::Metadata(metadata_title::NAME.into()),
+            Self::Keywords => EmbeddedField::Metadata(metadata_keywords::NAME.into()),
+        }
+    }
+
+    /// Dense searches of this vector score by cosine similarity; `title` uses
+    /// Manhattan distance, where lower is closer.
+    pub fn is_cosine(self) -> bool {
+        self != Self::Title
+    }
+}
+
+impl FromStr for NamedVector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "combined" => Ok(Self::Combined),
+            "chunk" => Ok(Self::Chunk),
+            "questions" => Ok(Self::Questions),
+            "summary" => Ok(Self::Summary),
+            "title" => Ok(Self::Title),
+            "keywords" => Ok(Self::Keywords),
+            _ => Err(Error::ConfigWrongFormat("SERVICE_RETRIEVAL_VECTORS")),
+        }
+    }
+}
+
+/// Per-request overrides of the configured retrieval defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetrievalParams {
+    pub mode: Option<RetrievalMode>,
+    pub top_k: Option<u64>,
+    pub dense_weight: Option<f32>,
+    pub sparse_weight: Option<f32>,
+    /// Named vectors to search and fuse, e.g. `["questions", "keywords"]`.
+    pub vectors: Option<Vec<NamedVector>>,
+    /// Forces the reranking stage on or off; on by default when a reranker is configured.
+    pub rerank: Option<bool>,
+    pub rerank_candidates: Option<u64>,
+    /// Documents kept after reranking.
+    pub top_n: Option<usize>,
+    /// Documents scoring below this are dropped; compared with `rerank_score` when reranked.
+    pub min_score: Option<f32>,
+}
+
+impl RetrievalParams {
+    /// Overwrites every field that `overrides` sets.
+    pub fn apply(&mut self, overrides: &RetrievalParams) {
+        let o = overrides.clone();
+        self.mode = o.mode.or(self.mode);
+        self.top_k = o.top_k.or(self.top_k);
+        self.dense_weight = o.dense_weight.or(self.dense_weight);
+        self.sparse_weight = o.sparse_weight.or(self.sparse_weight);
+        self.vectors = o.vectors.or(self.vectors.take());
+        self.rerank = o.rerank.or(self.rerank);
+        self.rerank_candidates = o.rerank_candidates.or(self.rerank_candidates);
+        self.top_n = o.top_n.or(self.top_n);
+        self.min_score = o.min_score.or(self.min_score);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetrievalStrategy {
+    pub mode: RetrievalMode,
+    pub top_k: u64,
+    pub dense_weight: f32,
+    pub sparse_weight: f32,
+    pub vectors: Vec<NamedVector>,
+    /// Candidate pool handed to the reranker instead of `top_k`.
+    pub rerank_candidates: u64,
+    /// `Some(top_n)` when the reranking stage runs.
+    pub rerank_top_n: Option<usize>,
+    pub min_score: Option<f32>,
+}
+
+impl SearchStrategy for RetrievalStrategy {}
+
+impl Default for RetrievalStrategy {
+    fn default() -> Self {
+        let config = config();
+        Self {
+            mode: config.RETRIEVAL_MODE,
+            top_k: config.RETRIEVAL_TOP_K,
+            dense_weight: config.HYBRID_DENSE_WEIGHT,
+            sparse_weight: config.HYBRID_SPARSE_WEIGHT,
+            vectors: config.RETRIEVAL_VECTORS
+                .iter()
+                .filter_map(|v| v.parse().ok())
+                .collect(),
+            rerank_candidates: config.RERANK_CANDIDATES,
+            rerank_top_n: config.RERANK_MODEL.is_some().then_some(config.RERANK_TOP_N),
+            min_score: config.MIN_RETRIEVAL_SCORE,
+        }
+    }
+}
+
+impl RetrievalStrategy {
+    /// Applies `params` on top of the defaults, rejecting stages whose model is
+    /// not configured.
+    pub fn from_params(params: &RetrievalParams, llm: &LlmRegistry) -> Result<Self> {
+        let sparse_available = llm.sparse().is_some();
+        let mut strategy = Self::default();
+        if !sparse_available && params.mode.is_none() {
+            strategy.mode = RetrievalMode::Dense;
+        }
+        if let Some(mode) = params.mode {
+            strategy.mode = mode;
+        }
+        if let Some(top_k) = params.top_k {
+            strategy.top_k = top_k;
+        }
+        if let Some(weight) = params.dense_weight {
+            strategy.dense_weight = weight;
+        }
+        if let Some(weight) = params.sparse_weight {
+            strategy.sparse_weight = weight;
+        }
+
+        if let Some(vectors) = &params.vectors {
+            strategy.vectors = vectors.clone();
+        }
+        // each vector is searched once, so none gets extra weight in the fusion
+        let mut seen = HashSet::new();
+        strategy.vectors.retain(|v| seen.insert(*v));
+        if let Some(min_score) = params.min_score {
+            strategy.min_score = Some(min_score);
+        }
+        if let Some(candidates) = params.rerank_candidates {
+            strategy.rerank_candidates = candidates;
+        }
+        let rerank = params.rerank.unwrap_or(strategy.rerank_top_n.is_some());
+        strategy.rerank_top_n = rerank.then(|| params.top_n.unwrap_or(config().RERANK_TOP_N));
+
+        if strategy.mode.uses_sparse() && !sparse_available {
+            return Err(Error::RetrievalModeUnavailable(format!("{:?}", strategy.mode)));
+        }
+        if rerank && llm.reranker().is_none() {
+            return Err(Error::RetrievalModeUnavailable("rerank".to_string()));
+        }
+        if strategy.top_k == 0
+            || strategy.vectors.is_empty()
+            || strategy.rerank_candidates == 0
+            || strategy.rerank_top_n == Some(0)
+            // NaN or infinite weights would poison every fused score
+            || ![strategy.dense_weight, strategy.sparse_weight]
+                .iter()
+                .all(|w| w.is_finite() && *w >= 0.0)
+        {
+            return Err(Error::RetrievalParamsInvalid);
+        }
+        Ok(strategy)
+    }
+
+    /// Number of documents fetched from Qdrant.
+    pub fn retrieval_limit(&self) -> u64 {
+        match self.rerank_top_n {
+            Some(_) => self.rerank_candidates,
+            None => self.top_k,
+        }
+    }
+}
+
+/// Retrieved document as returned to API callers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievedDocument {
+    pub title: String,
+    pub doc_id: Option<String>,
+    pub doc_name: Option<String>,
+    /// Similarity, or the fused RRF score when several searches ran.
+    pub score: Option<f64>,
+    pub rerank_score: Option<f64>,
+}
+
+impl From<&Document> for RetrievedDocument {
+    fn from(document: &Document) -> Self {
+        let metadata = document.metadata();
+        let text = |key: &str| metadata.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        Self {
+            title: text("Title").unwrap_or_default(),
+            doc_id: text("doc_id"),
+            doc_name: text("doc_name"),
+            score: metadata.get("score").and_then(|v| v.as_f64()),
+            rerank_score: metadata.get("rerank_score").and_then(|v| v.as_f64()),
+        }
+    }
+}
+
+/// Searches the selected named vectors of the collection, dense and/or sparse.
+#[derive(Debug, Clone)]
+pub struct QdrantRetriever {
+    qdrant: Qdrant,
+    /// Set for multi-query expansion: every line of the query is embedded and searched.
+    variants: Option<VariantEmbedders>,
+    /// Restricts every search to matching chunks.
+    filter: Option<Filter>,
+}
+
+#[derive(Debug, Clone)]
+struct VariantEmbedders {
+    embed: LlmClient,
+    sparse: Option<FastEmbed>,
+}
+
+impl QdrantRetriever {
+    pub fn new(qdrant: Qdrant) -> Self {
+        Self { qdrant, variants: None, filter: None }
+    }
+
+    /// Only searches chunks of the given documents (`doc_id`s) or file names.
+    pub fn with_documents(mut self, doc_ids: &[String], doc_names: &[String]) -> Self {
+        let mut conditions = Vec::new();
+        if !doc_ids.is_empty() {
+            conditions.push(Condition::matches("doc_id", doc_ids.to_vec()));
+        }
+        if !doc_names.is_empty() {
+            conditions.push(Condition::matches("doc_name", doc_names.to_vec()));
+        }
+        self.filter = (!conditions.is_empty()).then(|| Filter::should(conditions));
+        self
+    }
+
+    /// Embeds the query variants itself, so no `Embed` stage is needed in front.
+    pub fn with_variants(mut self, embed: LlmClient, sparse: Option<FastEmbed>) -> Self {
+        self.variants = Some(VariantEmbedders { embed, sparse });
+        self
+    }
+
+    /// Runs one search per selected vector and mode, fusing them when there is more than one.
+    pub async fn search_documents(
+        &self,
+        strategy: &RetrievalStrategy,
+        embedding: Option<Vec<f32>>,
+        sparse_embedding: Option<Vec<(u32, f32)>>,
+    ) -> anyhow::Result<Vec<Document>> {
+        let rankings = self.rankings(strategy, "", embedding, sparse_embedding).await?;
+        Ok(fuse(rankings, strategy.retrieval_limit() as usize))
+    }
+
+    /// Searches every variant and merges all rankings, so a chunk found by
+    /// several variants appears once with the summed RRF score.
+    async fn search_variants(
+        &self,
+        strategy: &RetrievalStrategy,
+        embedders: &VariantEmbedders,
+        variants: Vec<String>,
+    ) -> anyhow::Result<Vec<Document>> {
+        let embeddings: Vec<Option<Vec<f32>>> = match strategy.mode.uses_dense() {
+            true => embedders.embed.embed(variants.clone()).await?.into_iter().map(Some).collect(),
+            false => vec![None; variants.len()],
+        };
+        let sparse_embeddings: Vec<Option<Vec<(u32, f32)>>> = match (strategy.mode.uses_sparse(), &embedders.sparse) {
+            (true, Some(sparse)) => sparse
+                .sparse_embed(variants.clone())
+                .await?
+                .into_iter()
+                .map(|s| Some(s.indices.into_iter().zip(s.values).collect()))
+                .collect(),
+            _ => vec![None; variants.len()],
+        };
+
+        let mut rankings = Vec::new();
+        for (i, (embedding, sparse)) in embeddings.into_iter().zip(sparse_embeddings).enumerate() {
+            // the original question keeps the plain ranking names
+            let prefix = if i == 0 { String::new() } else { format!("variant{i}_") };
+            rankings.extend(self.rankings(strategy, &prefix, embedding, sparse).await?);
+        }
+        Ok(fuse(rankings, strategy.retrieval_limit() as usize))
+    }
+
+    async fn rankings(
+        &self,
+        strategy: &RetrievalStrategy,
+        prefix: &str,
+        embedding: Option<Vec<f32>>,
+        sparse_embedding: Option<Vec<(u32, f32)>>,
+    ) -> anyhow::Result<Vec<Ranking>> {
+        let limit = strategy.retrieval_limit();
+        let mut rankings = Vec::new();
+
+        for vector in &strategy.vectors {
+            let field = vector.embedded_field();
+            if strategy.mode.uses_dense() {
+                let embedding = embedding.clone().context("query has no dense embedding")?;
+                let points = self.search(field.field_name(), embedding, limit).await?;
+                rankings.push(Ranking {
+                    name: format!("{prefix}dense_{}", vector.as_ref()),
+                    weight: strategy.dense_weight,
+                    similarity: vector.is_cosine(),
+                    points,
+                });
+            }
+            if strategy.mode.uses_sparse() {
+                let sparse = sparse_embedding.clone().context("query has no sparse embedding")?;
+                let points = self.search(field.sparse_field_name(), sparse, limit).await?;
+                rankings.push(Ranking {
+                    name: format!("{prefix}sparse_{}", vector.as_ref()),
+                    weight: strategy.sparse_weight,
+                    similarity: false,
+                    points,
+                });
+            }
+        }
+
+        Ok(rankings)
+    }
+
+    async fn search(
+        &self,
+        using: String,
+        query: impl Into<qdrant_client::qdrant::Query>,
+        limit: u64,
+    ) -> anyhow::Result<Vec<ScoredPoint>> {
+        let mut request = QueryPointsBuilder::new(QDRANT_COLLECTION)
+            .query(query)
+            .using(using)
+            .limit(limit)
+            .with_payload(true);
+        if let Some(filter) = &self.filter {
+            request = request.filter(filter.clone());
+        }
+        let response = self.qdrant.client().query(request).await?;
+        Ok(response.result)
+    }
+}
+
+#[async_trait]
+impl Retrieve<RetrievalStrategy> for QdrantRetriever {
+    async fn retrieve(
+        &self,
+        strategy: &RetrievalStrategy,
+        query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        if let Some(embedders) = &self.variants {
+            let documents = self
+                .search_variants(strategy, embedders, query_variants(query.current()))
+                .await?;
+            return Ok(query.retrieved_documents(documents));
+        }
+
+        let sparse = query.sparse_embedding
+            .clone()
+            .map(|sparse| sparse.indices.into_iter().zip(sparse.values).collect());
+        let documents = self
+            .search_documents(strategy, query.embedding.clone(), sparse)
+            .await?;
+        Ok(query.retrieved_documents(documents))
+    }
+}
+
+pub(crate) struct Ranking {
+    pub name: String,
+    pub weight: f32,
+    /// Scores are cosine similarities, comparable with `min_score`. Sparse
+    /// dot products are unbounded and Manhattan distances grow with distance.
+    pub similarity: bool,
+    pub points: Vec<ScoredPoint>,
+}
+
+/// A point of the fused rankings.
+struct Fused {
+    score: f32,
+    /// Highest cosine similarity among the rankings the point appeared in.
+    best_score: Option<f32>,
+    point: ScoredPoint,
+    extra: Vec<(String, serde_json::Value)>,
+}
+
+/// Weighted reciprocal rank fusion. A single ranking keeps its raw score as
+/// `score`; fused results get the RRF score plus `<name>_score` and
+/// `<name>_rank` (e.g. `dense_questions_rank`) for every ranking the point
+/// appeared in. Results found by a cosine search also get the highest
+/// similarity among them as `best_score`.
+pub(crate) fn fuse(rankings: Vec<Ranking>, top_k: usize) -> Vec<Document> {
+    let single = rankings.len() == 1;
+    let mut fused: HashMap<String, Fused> = HashMap::new();
+
+    for ranking in rankings {
+        for (rank, point) in ranking.points.into_iter().enumerate() {
+            let key = format!("{:?}", point.id);
+            let rrf = ranking.weight / (RRF_K + rank as f32 + 1.0);
+            let similarity = ranking.similarity.then_some(point.score);
+            let extra = vec![
+                (format!("{}_score", ranking.name), point.score.into()),
+                (format!("{}_rank", ranking.name), (rank + 1).into()),
+            ];
+            let entry = fused.entry(key).or_insert_with(|| Fused {
+                score: 0.0,
+                best_score: None,
+                point: point.clone(),
+                extra: Vec::new(),
+            });
+            entry.score += if single { point.score } else { rrf };
+            entry.best_score = match (entry.best_score, similarity) {
+                (Some(best), Some(similarity)) => Some(best.max(similarity)),
+                (best, similarity) => best.or(similarity),
+            };
+            entry.extra.extend(extra);
+        }
+    }
+
+    let mut results: Vec<_> = fused.into_values().collect();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results
+        .into_iter()
+        .take(top_k)
+        .map(|Fused { score, best_score, point, mut extra }| {
+            if single {
+                extra.clear();
+            }
+            if let Some(best_score) = best_score {
+                extra.push(("best_score".to_string(), best_score.into()));
+            }
+            point_into_document(point, score, extra)
+        })
+        .collect()
+}
+
+/// Payload layout written by the Swiftide Qdrant storage: `content` plus the
+/// flattened node metadata.
+fn point_into_document(point: ScoredPoint, score: f32, extra: Vec<(String, serde_json::Value)>) -> Document {
+    let mut content = String::new();
+    let mut metadata = Metadata::default();
+    for (key, value) in point.payload {
+        let value = serde_json::Value::from(value);
+        if key == "content" {
+            content = value.as_str().unwrap_or_default().to_string();
+        } else {
+            metadata.insert(key, value);
+        }
+    }
+    for (key, value) in extra {
+        metadata.insert(key, value);
+    }
+    metadata.insert("score", score);
+    Document::new(content, Some(metadata))
+}
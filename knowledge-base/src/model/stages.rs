@@ -0,0 +1,269 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use swiftide::indexing::{IndexingDefaults, IndexingStream, Node};
+use swiftide::query::{states, Query};
+use swiftide::traits::{
+    Answer, BatchableTransformer, NodeCache, Retrieve, SearchStrategy, Transformer, TransformQuery,
+    TransformResponse, WithBatchIndexingDefaults, WithIndexingDefaults,
+};
+use tracing::field::Empty;
+use tracing::{info_span, Instrument, Span};
+
+use crate::metrics;
+use crate::model::chat_events::Progress;
+use crate::utils::token;
+
+/// Number of retrieval scores attached to a `retrieve` span.
+const TRACED_TOP_SCORES: usize = 5;
+
+/// Wraps a Swiftide stage (query or indexing) in its own tracing span and
+/// records its latency, failures and (tiktoken-estimated) token usage,
+/// labeled by pipeline, model and stage.
+#[derive(Debug, Clone)]
+pub struct Timed<T> {
+    inner: T,
+    pipeline: &'static str,
+    model: String,
+    stage: &'static str,
+    /// Chat clients told when the stage starts.
+    progress: Option<Progress>,
+}
+
+impl<T> Timed<T> {
+    pub fn new(inner: T, pipeline: &'static str, model: &str, stage: &'static str) -> Self {
+        Self {
+            inner,
+            pipeline,
+            model: model.to_string(),
+            stage,
+            progress: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: Option<Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    fn started(&self) {
+        if let Some(progress) = &self.progress {
+            progress.stage(self.stage);
+        }
+    }
+
+    fn span(&self) -> Span {
+        info_span!(
+            "rag.stage",
+            otel.name = %format!("{}.{}", self.pipeline, self.stage),
+            otel.status_code = Empty,
+            pipeline = self.pipeline,
+            stage = self.stage,
+            model = %self.model,
+            subquestions = Empty,
+            documents = Empty,
+            top_scores = Empty,
+            prompt_tokens = Empty,
+            completion_tokens = Empty,
+        )
+    }
+
+    fn record<R>(&self, span: &Span, start: Instant, result: &anyhow::Result<R>) {
+        metrics::record_stage(self.pipeline, &self.model, self.stage, start.elapsed(), result.is_ok());
+        if let Err(e) = result {
+            span.record("otel.status_code", "ERROR");
+            tracing::error!(parent: span, error = %e, "stage failed");
+        }
+    }
+
+    fn record_tokens(&self, span: &Span, prompt: &str, completion: &str) {
+        let prompt_tokens = token::count(prompt);
+        let completion_tokens = token::count(completion);
+        span.record("prompt_tokens", prompt_tokens);
+        span.record("completion_tokens", completion_tokens);
+        metrics::record_tokens(self.pipeline, &self.model, self.stage, "prompt", prompt_tokens);
+        metrics::record_tokens(self.pipeline, &self.model, self.stage, "completion", completion_tokens);
+    }
+}
+
+#[async_trait]
+impl<T: TransformQuery + Clone> TransformQuery for Timed<T> {
+    async fn transform_query(
+        &self,
+        query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Pending>> {
+        self.started();
+        let span = self.span();
+        let prompt = query.current().to_string();
+        let start = Instant::now();
+        let result = self.inner.transform_query(query).instrument(span.clone()).await;
+        self.record(&span, start, &result);
+
+        if let Ok(query) = &result {
+            // Subquestion-style transformers emit the original question
+            // followed by one generated question per line.
+            if query.current() != prompt {
+                let generated = query.current().lines().filter(|l| !l.trim().is_empty()).count();
+                span.record("subquestions", generated.saturating_sub(1));
+            }
+            self.record_tokens(&span, &prompt, query.current());
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<S: SearchStrategy, T: Retrieve<S> + Clone> Retrieve<S> for Timed<T> {
+    async fn retrieve(
+        &self,
+        search_strategy: &S,
+        query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        self.started();
+        let span = self.span();
+        let start = Instant::now();
+        let result = self.inner.retrieve(search_strategy, query).instrument(span.clone()).await;
+        self.record(&span, start, &result);
+
+        if let Ok(query) = &result {
+            let documents = query.documents();
+            span.record("documents", documents.len());
+
+            let top_scores = documents
+                .iter()
+                .filter_map(|d| d.metadata().get("score").and_then(|s| s.as_f64()))
+                .take(TRACED_TOP_SCORES)
+                .map(|s| format!("{s:.4}"))
+                .collect::<Vec<_>>();
+            if !top_scores.is_empty() {
+                span.record("top_scores", top_scores.join(","));
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<T: TransformResponse + Clone> TransformResponse for Timed<T> {
+    async fn transform_response(
+        &self,
+        query: Query<states::Retrieved>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        self.started();
+        let span = self.span();
+        span.record("documents", query.documents().len());
+        let prompt: String = query.documents().iter().map(|d| d.content()).collect();
+        let start = Instant::now();
+        let result = self.inner.transform_response(query).instrument(span.clone()).await;
+        self.record(&span, start, &result);
+
+        if let Ok(query) = &result {
+            self.record_tokens(&span, &prompt, query.current());
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<T: Answer + Clone> Answer for Timed<T> {
+    async fn answer(
+        &self,
+        query: Query<states::Retrieved>,
+    ) -> anyhow::Result<Query<states::Answered>> {
+        self.started();
+        let span = self.span();
+        let prompt = query.current().to_string();
+        let start = Instant::now();
+        let result = self.inner.answer(query).instrument(span.clone()).await;
+        self.record(&span, start, &result);
+
+        if let Ok(query) = &result {
+            self.record_tokens(&span, &prompt, query.answer());
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<T: Transformer + Clone> Transformer for Timed<T> {
+    async fn transform_node(&self, node: Node) -> anyhow::Result<Node> {
+        let span = self.span();
+        let prompt = node.chunk.clone();
+        let known_keys: Vec<String> = node.metadata.iter().map(|(k, _)| k.to_string()).collect();
+        let start = Instant::now();
+        let result = self.inner.transform_node(node).instrument(span.clone()).await;
+        self.record(&span, start, &result);
+
+        // Only count metadata added by this stage, not by the ones before it.
+        if let Ok(node) = &result {
+            let generated: String = node.metadata
+                .iter()
+                .filter(|(k, _)| !known_keys.iter().any(|known| known == *k))
+                .filter_map(|(_, v)| v.as_str())
+                .collect();
+            self.record_tokens(&span, &prompt, &generated);
+        }
+        result
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.inner.concurrency()
+    }
+}
+
+#[async_trait]
+impl<T: BatchableTransformer + Clone> BatchableTransformer for Timed<T> {
+    async fn batch_transform(&self, nodes: Vec<Node>) -> IndexingStream {
+        let span = self.span();
+        span.record("documents", nodes.len());
+        let start = Instant::now();
+        let stream = self.inner.batch_transform(nodes).instrument(span.clone()).await;
+        metrics::record_stage(self.pipeline, &self.model, self.stage, start.elapsed(), true);
+        stream
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.inner.batch_size()
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.inner.concurrency()
+    }
+}
+
+impl<T: WithIndexingDefaults> WithIndexingDefaults for Timed<T> {
+    fn with_indexing_defaults(&mut self, defaults: IndexingDefaults) {
+        self.inner.with_indexing_defaults(defaults);
+    }
+}
+
+impl<T: WithBatchIndexingDefaults> WithBatchIndexingDefaults for Timed<T> {
+    fn with_indexing_defaults(&mut self, defaults: IndexingDefaults) {
+        self.inner.with_indexing_defaults(defaults);
+    }
+}
+
+/// Node cache decorator counting hits and misses of `filter_cached`.
+#[derive(Debug, Clone)]
+pub struct CountingCache<C> {
+    inner: C,
+}
+
+impl<C> CountingCache<C> {
+    pub fn new(inner: C) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl<C: NodeCache + Clone> NodeCache for CountingCache<C> {
+    async fn get(&self, node: &Node) -> bool {
+        let hit = self.inner.get(node).await;
+        metrics::record_cache_lookup(hit);
+        hit
+    }
+
+    async fn set(&self, node: &Node) {
+        self.inner.set(node).await
+    }
+}
@@ -1,7 +1,7 @@
 use crate::ctx::Ctx;
 use crate::model::base::{self, DbBmc};
 use crate::model::manager::ModelManager;
-use crate::error::{Result};
+use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use modql::field::Fields;
 use sqlx::FromRow;
@@ -12,6 +12,12 @@ pub struct Task {
     pub id: i64,
     pub title: String,
     pub created_by: i64,
+    /// Document the task was extracted from as an action item.
+    pub source_document_id: Option<i64>,
+    /// Conversation message the task was extracted from as an action item.
+    pub source_message_id: Option<i64>,
+    /// Words of the source the action item was taken from.
+    pub source_quote: Option<String>,
 }
 
 #[derive(Debug, Fields, Deserialize)]
@@ -19,10 +25,13 @@ pub struct TaskForCreate {
     pub title: String,
 }
 
-#[derive(Debug, Fields, Deserialize)]
+#[derive(Debug, Default, Fields, Deserialize)]
 pub struct TaskForCreateInternal {
     pub title: String,
     pub created_by: i64,
+    pub source_document_id: Option<i64>,
+    pub source_message_id: Option<i64>,
+    pub source_quote: Option<String>,
 }
 
 #[derive(Debug, Fields, Deserialize)]
@@ -47,14 +56,28 @@ impl TaskBmc {
         Self::get(ctx, mm, id).await
     }
 
+    /// Task of the caller. Tasks of other users are not found: action items
+    /// carry text from the caller's private conversations.
     #[instrument]
     pub async fn get(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<Task> {
-        base::get::<Self, _>(ctx, mm, id).await
+        let task: Task = base::get::<Self, _>(ctx, mm, id).await?;
+        if task.created_by != ctx.user_id() {
+            return Err(Error::EntityNotFound { entity: Self::TABLE, id });
+        }
+        Ok(task)
     }
 
+    /// Tasks created by the caller.
     #[instrument]
-    pub async fn list(ctx: &Ctx, mm: &ModelManager) -> Result<Vec<Task>> {
-        base::list::<Self, _>(ctx, mm).await
+    pub async fn list_for_user(ctx: &Ctx, mm: &ModelManager) -> Result<Vec<Task>> {
+        let tasks = sqlx::query_as::<_, Task>(
+            "SELECT id, title, created_by, source_document_id, source_message_id, source_quote \
+             FROM task WHERE created_by = $1 ORDER BY id"
+        )
+            .bind(ctx.user_id())
+            .fetch_all(mm.db())
+            .await?;
+        Ok(tasks)
     }
 
     #[instrument]
@@ -64,6 +87,7 @@ impl TaskBmc {
         id: i64,
         task_u: TaskForUpdate,
     ) -> Result<Task> {
+        Self::get(ctx, mm, id).await?;
         base::update::<Self, _>(ctx, mm, id, task_u).await?;
         Self::get(ctx, mm, id).await
     }
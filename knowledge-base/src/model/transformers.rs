@@ -0,0 +1,291 @@
+//! Query and answer stages of our own, driven by the configured prompt model.
+//! Their prompts can be replaced through the prompt template store.
+
+use async_trait::async_trait;
+use swiftide::prompt::Prompt;
+use swiftide::query::{states, Document, Query};
+use swiftide::traits::{Answer, SimplePrompt, TransformQuery, TransformResponse};
+
+use crate::model::chat_events::Progress;
+use crate::model::llm::LlmClient;
+
+const REWRITE_PROMPT: &str = "Rewrite the following question into a single, self-contained search query \
+for an internal knowledge base. Expand abbreviations you are sure about and keep error codes, \
+identifiers and product names verbatim. Reply with the rewritten query only.\n\nQuestion: {{ question }}";
+
+const HYDE_PROMPT: &str = "Write a short passage, as it could appear in an internal knowledge base \
+article, that answers the following question. Invent plausible details if you have to, it is only \
+used to search for similar documents. Reply with the passage only.\n\nQuestion: {{ question }}";
+
+const EXPAND_PROMPT: &str = "Write {{ num_variants }} different search queries for an internal knowledge base that \
+would find documents answering the following question. Vary wording and use likely synonyms, keep \
+error codes, identifiers and product names verbatim. Reply with one query per line and nothing else.\n\nQuestion: {{ question }}";
+
+/// Answer prompt for chat answers when no template is configured, since the
+/// Swiftide default cannot render the conversation history.
+pub const CHAT_ANSWER_PROMPT: &str = "You answer questions in an ongoing conversation, using the \
+context retrieved from an internal knowledge base. Use the conversation so far to resolve \
+references such as \"it\" or \"the second option\". If the context does not contain the answer, say so.\n\n\
+Conversation so far:\n{{ history }}\n\nContext:\n{{ context }}\n\nQuestion: {{ question }}";
+
+/// Replaces the question with a search-friendly rewrite.
+#[derive(Debug, Clone)]
+pub struct RewriteQuery {
+    client: LlmClient,
+    template: String,
+}
+
+impl RewriteQuery {
+    pub fn new(client: LlmClient) -> Self {
+        Self { client, template: REWRITE_PROMPT.to_string() }
+    }
+
+    pub fn with_template(self, template: Option<String>) -> Self {
+        Self { template: template.unwrap_or(self.template), ..self }
+    }
+}
+
+#[async_trait]
+impl TransformQuery for RewriteQuery {
+    async fn transform_query(
+        &self,
+        mut query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Pending>> {
+        let prompt = Prompt::from(self.template.clone()).with_context_value("question", query.current());
+        let rewritten = self.client.prompt(prompt).await?;
+        let rewritten = rewritten.trim();
+        if !rewritten.is_empty() {
+            query.transformed_query(rewritten);
+        }
+        Ok(query)
+    }
+}
+
+/// HyDE: replaces the question with a generated hypothetical answer, which
+/// embeds closer to the documents than a short question does.
+#[derive(Debug, Clone)]
+pub struct HypotheticalAnswer {
+    client: LlmClient,
+    template: String,
+}
+
+impl HypotheticalAnswer {
+    pub fn new(client: LlmClient) -> Self {
+        Self { client, template: HYDE_PROMPT.to_string() }
+    }
+
+    pub fn with_template(self, template: Option<String>) -> Self {
+        Self { template: template.unwrap_or(self.template), ..self }
+    }
+}
+
+#[async_trait]
+impl TransformQuery for HypotheticalAnswer {
+    async fn transform_query(
+        &self,
+        mut query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Pending>> {
+        let prompt = Prompt::from(self.template.clone()).with_context_value("question", query.current());
+        let passage = self.client.prompt(prompt).await?;
+        let passage = passage.trim();
+        if !passage.is_empty() {
+            query.transformed_query(passage);
+        }
+        Ok(query)
+    }
+}
+
+/// Multi-query expansion: appends up to `variants` rephrasings of the question,
+/// one per line. `QdrantRetriever::with_variants` searches each line separately
+/// and merges the results.
+#[derive(Debug, Clone)]
+pub struct ExpandQuery {
+    client: LlmClient,
+    variants: usize,
+    template: String,
+}
+
+impl ExpandQuery {
+    pub fn new(client: LlmClient, variants: usize) -> Self {
+        Self { client, variants, template: EXPAND_PROMPT.to_string() }
+    }
+
+    pub fn with_template(self, template: Option<String>) -> Self {
+        Self { template: template.unwrap_or(self.template), ..self }
+    }
+}
+
+#[async_trait]
+impl TransformQuery for ExpandQuery {
+    async fn transform_query(
+        &self,
+        mut query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Pending>> {
+        let question = query.current().trim().to_string();
+        let prompt = Prompt::from(self.template.clone())
+            .with_context_value("question", question.as_str())
+            .with_context_value("num_variants", self.variants);
+        let reply = self.client.prompt(prompt).await?;
+
+        let mut queries = vec![question];
+        for line in reply.lines() {
+            let line = line
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')'))
+                .trim();
+            if queries.len() > self.variants {
+                break;
+            }
+            if !line.is_empty() && !queries.iter().any(|q| q.eq_ignore_ascii_case(line)) {
+                queries.push(line.to_string());
+            }
+        }
+        query.transformed_query(queries.join("\n"));
+        Ok(query)
+    }
+}
+
+/// Splits a query produced by `ExpandQuery` back into its variants.
+pub fn query_variants(query: &str) -> Vec<String> {
+    query
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Answers with a caller-provided template rendered with `question` and `context`.
+#[derive(Debug, Clone)]
+pub struct TemplatedAnswer {
+    client: LlmClient,
+    template: String,
+    /// Use the transformed response (e.g. the summary) instead of the raw documents.
+    use_response: bool,
+    /// Conversation so far, rendered as `{{ history }}`.
+    history: Option<String>,
+    /// Streams the answer to chat clients while it is generated.
+    progress: Option<Progress>,
+}
+
+impl TemplatedAnswer {
+    pub fn new(client: LlmClient, template: String, use_response: bool) -> Self {
+        Self { client, template, use_response, history: None, progress: None }
+    }
+
+    pub fn with_history(mut self, history: Option<String>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Option<Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+#[async_trait]
+impl Answer for TemplatedAnswer {
+    async fn answer(&self, query: Query<states::Retrieved>) -> anyhow::Result<Query<states::Answered>> {
+        let context = match self.use_response {
+            true => query.current().to_string(),
+            false => query
+                .documents()
+                .iter()
+                .map(|d| d.content())
+                .collect::<Vec<_>>()
+                .join("\n---\n"),
+        };
+        let prompt = Prompt::from(self.template.clone())
+            .with_context_value("question", query.original())
+            .with_context_value("context", context)
+            .with_context_value("history", self.history.clone().unwrap_or_default());
+
+        let answer = match &self.progress {
+            Some(progress) => self.client.prompt_streaming(prompt, |delta| progress.token(delta)).await?,
+            None => self.client.prompt(prompt).await?,
+        };
+        Ok(query.answered(answer))
+    }
+}
+
+/// Drops retrieved documents scoring below `min_score`: `rerank_score` when
+/// the reranker ran, otherwise the best cosine similarity (`best_score`).
+/// Documents only found by sparse or `title` searches have no similarity on
+/// that scale and are dropped.
+#[derive(Debug, Clone)]
+pub struct RelevanceFilter {
+    min_score: f32,
+}
+
+impl RelevanceFilter {
+    pub fn new(min_score: f32) -> Self {
+        Self { min_score }
+    }
+
+    pub fn retain(&self, documents: &mut Vec<Document>) {
+        let min_score = f64::from(self.min_score);
+        documents.retain(|d| relevance(d).is_some_and(|score| score >= min_score));
+    }
+}
+
+fn relevance(document: &Document) -> Option<f64> {
+    let metadata = document.metadata();
+    metadata
+        .get("rerank_score")
+        .or_else(|| metadata.get("best_score"))
+        .and_then(|v| v.as_f64())
+}
+
+#[async_trait]
+impl TransformResponse for RelevanceFilter {
+    async fn transform_response(
+        &self,
+        mut query: Query<states::Retrieved>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        self.retain(query.documents_mut());
+        Ok(query)
+    }
+}
+
+/// Runs the inner stage only when documents are left. Without any, response
+/// stages pass the query through and answer stages reply with `fallback`
+/// instead of letting the model answer from nothing.
+#[derive(Debug, Clone)]
+pub struct WhenDocuments<T> {
+    inner: T,
+    fallback: String,
+}
+
+impl<T> WhenDocuments<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner, fallback: String::new() }
+    }
+
+    pub fn with_fallback(self, fallback: String) -> Self {
+        Self { fallback, ..self }
+    }
+}
+
+#[async_trait]
+impl<T: TransformResponse + Clone> TransformResponse for WhenDocuments<T> {
+    async fn transform_response(
+        &self,
+        query: Query<states::Retrieved>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        if query.documents().is_empty() {
+            return Ok(query);
+        }
+        self.inner.transform_response(query).await
+    }
+}
+
+#[async_trait]
+impl<T: Answer + Clone> Answer for WhenDocuments<T> {
+    async fn answer(&self, query: Query<states::Retrieved>) -> anyhow::Result<Query<states::Answered>> {
+        if query.documents().is_empty() {
+            return Ok(query.answered(self.fallback.clone()));
+        }
+        self.inner.answer(query).await
+    }
+}
@@ -1,5 +1,5 @@
 use opentelemetry::{runtime, KeyValue};
-use opentelemetry::trace::TraceError;
+use opentelemetry::trace::{TraceContextExt, TraceError, TraceId};
 use opentelemetry::sdk::{Resource, trace};
 use opentelemetry_otlp::WithExportConfig;
 
@@ -16,3 +16,11 @@ pub fn init_trace() -> Result<trace::Tracer, TraceError> {
         )
         .install_batch(runtime::Tokio)
 }
+
+/// Trace id of the current span, if it belongs to a sampled OpenTelemetry trace.
+pub fn current_trace_id() -> Option<String> {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let trace_id = tracing::Span::current().context().span().span_context().trace_id();
+    (trace_id != TraceId::INVALID).then(|| trace_id.to_string())
+}
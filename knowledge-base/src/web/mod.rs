@@ -6,6 +6,7 @@ use crate::crypt::token::generate_token;
 pub mod routes_login;
 pub mod routes_task;
 pub mod mw_auth;
+pub mod mw_trace;
 pub mod routes_document;
 pub mod routes_query_data;
 pub mod routes_statistics;
@@ -13,6 +14,12 @@ pub mod routes_fine_tune;
 pub mod routes_user;
 pub mod routes_register;
 pub mod routes_chat;
+pub mod routes_chat_ws;
+pub mod routes_retrieval;
+pub mod routes_eval;
+pub mod routes_prompt;
+pub mod routes_feedback;
+pub mod routes_share;
 
 pub const AUTH_TOKEN:&str="auth-token";
 fn set_token_cookie(cookies: &Cookies, user: &str, salt: &str) -> Result<()> {
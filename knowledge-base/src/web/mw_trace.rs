@@ -0,0 +1,43 @@
+use axum::body::Body;
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use tracing::field::Empty;
+use tracing::{info_span, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Opens the root span of every request, continuing the caller's trace when a
+/// W3C `traceparent` header is present.
+pub async fn mw_trace_context(req: Request<Body>, next: Next) -> Response {
+    let parent_cx = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+
+    let span = info_span!(
+        "http_request",
+        otel.name = %format!("{} {}", req.method(), req.uri().path()),
+        otel.kind = "server",
+        http.method = %req.method(),
+        http.target = %req.uri(),
+        http.status_code = Empty,
+    );
+    span.set_parent(parent_cx);
+
+    let res = next.run(req).instrument(span.clone()).await;
+    span.record("http.status_code", res.status().as_u16());
+    res
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
@@ -1,16 +1,28 @@
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Json,
 };
 use serde::{Deserialize, Serialize};
 use tokio::spawn;
-use axum::http::StatusCode;
-use crate::{Ctx, Result};
-use crate::model::manager::ModelManager;
+use tracing::{warn, Instrument};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use crate::{Ctx, Error, Result};
+use crate::model::manager::{ChatTurn, ModelManager};
+use crate::config::config;
 use crate::model::chat::*;
+use crate::model::chat_export::{self, ChatExportBmc, ConversationExport, ExportFormat};
+use crate::model::chat_events::{CancelRequest, ChatEvent, Progress};
+use crate::model::chat_memory;
+use crate::model::chat_share::{Share, ShareBmc, ShareForCreate};
+use crate::model::feedback::{Feedback, FeedbackBmc, FeedbackForCreate};
+use crate::model::pipeline::PipelineParams;
+use crate::model::prompt::PromptSet;
+use crate::model::user::{User, UserBmc};
 use crate::utils::token;
+use uuid::Uuid;
 
 
 #[derive(Serialize)]
@@ -21,14 +33,72 @@ struct ConvList {
 #[derive(Deserialize)]
 struct NewConv {
     title: Option<String>,
+    /// Prompt model for every answer in this conversation; the server default when absent.
+    model: Option<String>,
 }
 #[derive(Deserialize)]
-struct SendReq {
+struct ExportParams {
+    /// `md`, `json` or `html`; JSON when absent.
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Deserialize)]
+struct ListConvParams {
+    /// List archived conversations instead of the current ones.
+    #[serde(default)]
+    archived: bool,
+    /// List the conversations other users shared with the caller instead.
+    #[serde(default)]
+    shared: bool,
+}
+#[derive(Deserialize)]
+struct CollaboratorReq {
+    role: CollaboratorRole,
+}
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<i64>,
+}
+#[derive(Deserialize)]
+pub(crate) struct SendReq {
     prompt: String,
+    /// Overrides the conversation model for this prompt only.
+    model: Option<String>,
+    /// Answer with tools (search, documents, tasks, calculator) instead of one retrieval pass.
+    #[serde(default)]
+    agent: bool,
+    #[serde(flatten)]
+    pipeline: PipelineParams,
+}
+#[derive(Deserialize)]
+struct RegenerateReq {
+    model: Option<String>,
+    #[serde(default)]
+    agent: bool,
+    #[serde(flatten)]
+    pipeline: PipelineParams,
+}
+#[derive(Deserialize)]
+struct SelectBranchReq {
+    message_id: i64,
 }
 #[derive(Serialize)]
 struct SendRes {
     answer: String,
+    /// Prompt being answered; its answer is added below it once ready.
+    message_id: i64,
+    /// Cancels the answer with `DELETE /chat/generations/:id`.
+    generation_id: Uuid,
+}
+/// Message on the active branch, with the alternatives it can be switched to.
+#[derive(Serialize)]
+struct PathMessage {
+    #[serde(flatten)]
+    message: Message,
+    /// Ids of the messages sharing this one's parent (itself included), oldest first.
+    siblings: Vec<i64>,
 }
 
 pub async fn create_conv(
@@ -36,10 +106,13 @@ pub async fn create_conv(
     ctx: Ctx,
     Json(body): Json<NewConv>,
 ) -> Result<Json<Conversation>> {
+    // reject unknown models up front rather than on the first message
+    mm.llm.prompt_for(body.model.as_deref())?;
     let id = ConversationBmc::create(
         &ctx,
         &mm,
-        body.title.as_deref().unwrap_or("Untitled"),
+        body.title.as_deref().unwrap_or(DEFAULT_TITLE),
+        body.model.as_deref(),
     )
         .await?;
     Ok(Json(ConversationBmc::get(&ctx, &mm, id).await?))
@@ -48,20 +121,194 @@ pub async fn create_conv(
 pub async fn list_conv(
     State(mm): State<ModelManager>,
     ctx: Ctx,
+    Query(params): Query<ListConvParams>,
 ) -> Result<Json<ConvList>> {
-    let conversations = ConversationBmc::list_for_user(&ctx, &mm).await?;
+    let conversations = match params.shared {
+        true => ConversationBmc::list_shared_with_user(&ctx, &mm).await?,
+        false => ConversationBmc::list_for_user(&ctx, &mm, params.archived).await?,
+    };
     Ok(Json(ConvList { conversations }))
 }
 
+/// Renames, archives or pins a conversation, or changes its model.
+pub async fn update_conv(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<ConversationForUpdate>,
+) -> Result<Json<Conversation>> {
+    if body.model.is_some() {
+        mm.llm.prompt_for(body.model.as_deref())?;
+    }
+    Ok(Json(ConversationBmc::update(&ctx, &mm, id, body).await?))
+}
+
+pub async fn delete_conv(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<StatusCode> {
+    ConversationBmc::delete(&ctx, &mm, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Searches the user's conversation titles and messages.
+pub async fn search(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Vec<SearchHit>>> {
+    if params.q.trim().is_empty() {
+        return Err(Error::ConversationInvalid("search query must not be empty".to_string()));
+    }
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+    Ok(Json(ConversationBmc::search(&ctx, &mm, &params.q, limit).await?))
+}
+
 pub async fn list_msgs(
     State(mm): State<ModelManager>,
     ctx: Ctx,
     Path(id): Path<i64>,
-) -> Result<Json<Vec<Message>>> {
-    // enforce ownership
-    ConversationBmc::get::<Conversation>(&ctx, &mm, id).await?;
-    let msgs = MessageBmc::recent(&ctx, &mm, id, 32_768).await?;
-    Ok(Json(msgs))
+) -> Result<Json<Vec<PathMessage>>> {
+    Ok(Json(active_path(&ctx, &mm, id).await?))
+}
+
+async fn active_path(ctx: &Ctx, mm: &ModelManager, conv_id: i64) -> Result<Vec<PathMessage>> {
+    let msgs = MessageBmc::active_path(ctx, mm, conv_id, 32_768).await?;
+    let tree = MessageBmc::tree(ctx, mm, conv_id).await?;
+    Ok(msgs
+        .into_iter()
+        .map(|message| PathMessage { siblings: tree.siblings(&message), message })
+        .collect())
+}
+
+/// Prompt to answer in the background.
+struct PendingAnswer {
+    conv: Conversation,
+    prompt_id: i64,
+    prompt: String,
+    model: Option<String>,
+    agent: bool,
+    pipeline: PipelineParams,
+    /// Active leaf the answer continues; the branch only moves on to the
+    /// answer if the user has not switched branches in the meantime.
+    expected_leaf: Option<i64>,
+}
+
+impl PendingAnswer {
+    /// Answers in the background. Clients following the conversation get
+    /// the progress. Until the answer is ready it can be cancelled with the
+    /// returned generation id, which keeps what was generated so far.
+    fn spawn(self, mm: &ModelManager, ctx: &Ctx) -> Uuid {
+        let mm = mm.clone();
+        let ctx = ctx.clone();
+        let (generation_id, cancelled) = mm.chat_hub.start(self.conv.id, self.prompt_id);
+        spawn(async move {
+            let progress = mm.chat_hub.progress(self.conv.id, self.prompt_id);
+            // a cancel request drops the answer future, which stops the generation
+            let result = tokio::select! {
+                result = self.answer(&mm, &ctx, &progress, generation_id) => result,
+                Ok(stored) = cancelled => self.store_cancelled(&mm, &ctx, &progress, stored).await,
+            };
+            mm.chat_hub.finish(generation_id);
+            if let Err(e) = result {
+                warn!("conversation {}: answering message {} failed: {e}", self.conv.id, self.prompt_id);
+                let error = e.client_status_and_error().1.as_ref().to_string();
+                mm.chat_hub.publish(self.conv.id, ChatEvent::Failed { prompt_id: self.prompt_id, error });
+            }
+        }.in_current_span());
+        generation_id
+    }
+
+    async fn answer(&self, mm: &ModelManager, ctx: &Ctx, progress: &Progress, generation_id: Uuid) -> Result<()> {
+        let conv_id = self.conv.id;
+        let llm = mm.llm.prompt_for(self.model.as_deref())?;
+        let prompts = PromptSet::load(ctx, mm).await?;
+        let history = chat_memory::history(ctx, mm, &self.conv, self.prompt_id, &llm, &prompts)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("conversation {conv_id}: loading the history failed: {e}");
+                None
+            });
+        let turn = ChatTurn { history: history.as_deref(), progress: Some(progress) };
+        let answer = match self.agent {
+            true => mm.agent_answer(ctx, &self.prompt, turn, self.model.as_deref(), &self.pipeline).await?,
+            false => mm.chat_answer(ctx, &self.prompt, turn, self.model.as_deref(), &self.pipeline).await?,
+        };
+
+        // the answer is complete, cancelling now would only lose it
+        if !mm.chat_hub.finish(generation_id) {
+            // cancelled meanwhile: the cancel branch in `spawn` stores the partial answer
+            return std::future::pending().await;
+        }
+        let answer_id = MessageBmc::add_answer(ctx, mm, conv_id, self.prompt_id, &answer).await?;
+        self.publish_answer(mm, ctx, answer_id).await?;
+
+        // the first exchange names the conversation
+        if history.is_none() && self.conv.title == DEFAULT_TITLE && config().CHAT_AUTO_TITLE && !answer.fallback {
+            chat_memory::generate_title(ctx, mm, conv_id, &llm, &prompts, &self.prompt, &answer.answer).await;
+        }
+        Ok(())
+    }
+
+    async fn store_cancelled(&self, mm: &ModelManager, ctx: &Ctx, progress: &Progress, stored: CancelRequest) -> Result<()> {
+        let answer_id = MessageBmc::add_cancelled(ctx, mm, self.conv.id, self.prompt_id, &progress.partial()).await?;
+        let _ = stored.send(answer_id);
+        self.publish_answer(mm, ctx, answer_id).await
+    }
+
+    async fn publish_answer(&self, mm: &ModelManager, ctx: &Ctx, answer_id: i64) -> Result<()> {
+        let _ = ConversationBmc::advance_leaf(ctx, mm, self.conv.id, self.expected_leaf, answer_id).await;
+        let message = MessageBmc::get_in(ctx, mm, self.conv.id, answer_id).await?;
+        mm.chat_hub.publish(self.conv.id, ChatEvent::Answer { prompt_id: self.prompt_id, message });
+        Ok(())
+    }
+}
+
+/// Tells the clients following the conversation about a new prompt.
+async fn publish_prompt(ctx: &Ctx, mm: &ModelManager, conv_id: i64, prompt_id: i64) -> Result<()> {
+    let message = MessageBmc::get_in(ctx, mm, conv_id, prompt_id).await?;
+    mm.chat_hub.publish(conv_id, ChatEvent::Message { message });
+    Ok(())
+}
+
+/// Stores a prompt on the active branch and starts answering it. Returns
+/// the prompt's message id and the generation id of its answer.
+pub(crate) async fn send_prompt(ctx: &Ctx, mm: &ModelManager, id: i64, body: SendReq) -> Result<(i64, Uuid)> {
+    let conv = ConversationBmc::get_with_access(ctx, mm, id, Access::Write).await?;
+    let model = body.model.or_else(|| conv.model.clone());
+    mm.llm.prompt_for(model.as_deref())?;
+    body.pipeline.resolve()?.config.validate(&mm.llm)?;
+
+    // 1) persist the user’s prompt immediately, continuing the active branch
+    let prompt_id = MessageBmc::add(ctx, mm, id, conv.active_leaf_id, "user", &body.prompt, token::count(&body.prompt)).await?;
+    ConversationBmc::set_active_leaf(ctx, mm, id, prompt_id).await?;
+    publish_prompt(ctx, mm, id, prompt_id).await?;
+
+    // 2) fire off the LLM & persistence in background
+    let generation_id = PendingAnswer {
+        conv,
+        prompt_id,
+        prompt: body.prompt,
+        model,
+        agent: body.agent,
+        pipeline: body.pipeline,
+        expected_leaf: Some(prompt_id),
+    }.spawn(mm, ctx);
+    Ok((prompt_id, generation_id))
+}
+
+/// Stops a generation and returns the message holding its partial answer,
+/// marked `cancelled`. The prompt stays.
+pub(crate) async fn cancel_generation(ctx: &Ctx, mm: &ModelManager, generation_id: Uuid) -> Result<Message> {
+    let not_running = || Error::ChatMessageInvalid(format!("generation {generation_id} is not running"));
+    let conv_id = mm.chat_hub.conversation_of(generation_id).ok_or_else(not_running)?;
+    ConversationBmc::require(ctx, mm, conv_id, Access::Write).await?;
+    let stored = mm.chat_hub.cancel(generation_id).ok_or_else(not_running)?;
+    let answer_id = stored
+        .await
+        .map_err(|_| Error::ServiceError(format!("generation {generation_id}: storing the partial answer failed")))?;
+    MessageBmc::get_in(ctx, mm, conv_id, answer_id).await
 }
 
 pub async fn send_msg(
@@ -70,31 +317,241 @@ pub async fn send_msg(
     Path(id): Path<i64>,
     Json(body): Json<SendReq>,
 ) -> Result<(StatusCode, Json<SendRes>)> {
-    // 1) persist the user’s prompt immediately
-    MessageBmc::add(&ctx, &mm, id, "user", &body.prompt, token::count(&body.prompt)).await?;
+    let (prompt_id, generation_id) = send_prompt(&ctx, &mm, id, body).await?;
 
-    // 2) fire off the LLM & persistence in background
-    let mm2 = mm.clone();
-    let ctx2 = ctx.clone();
-    let prompt = body.prompt.clone();
-    spawn(async move {
-        if let Ok(answer) = mm2.fine_tune_prompt(&ctx2, &prompt).await {
-            let _ = MessageBmc::add(
-                &ctx2, &mm2, id, "assistant", &answer, token::count(&answer)
-            ).await;
-        }
-    });
+    // immediately return 202 Accepted (no answer payload)
+    Ok((StatusCode::ACCEPTED, Json(SendRes { answer: String::new(), message_id: prompt_id, generation_id })))
+}
+
+/// Answers an assistant message's prompt again. The earlier answer stays
+/// as an alternative.
+pub async fn regenerate_msg(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((id, msg_id)): Path<(i64, i64)>,
+    Json(body): Json<RegenerateReq>,
+) -> Result<(StatusCode, Json<SendRes>)> {
+    let conv = ConversationBmc::get_with_access(&ctx, &mm, id, Access::Write).await?;
+    let answer = MessageBmc::get_in(&ctx, &mm, id, msg_id).await?;
+    let prompt_id = match (answer.sender.as_str(), answer.parent_id) {
+        ("assistant", Some(parent_id)) => parent_id,
+        _ => return Err(Error::ChatMessageInvalid("only assistant answers can be regenerated".to_string())),
+    };
+    let prompt = MessageBmc::get_in(&ctx, &mm, id, prompt_id).await?;
+    let model = body.model.or_else(|| conv.model.clone());
+    mm.llm.prompt_for(model.as_deref())?;
+    body.pipeline.resolve()?.config.validate(&mm.llm)?;
+
+    let expected_leaf = conv.active_leaf_id;
+    let generation_id = PendingAnswer {
+        conv,
+        prompt_id,
+        prompt: prompt.content,
+        model,
+        agent: body.agent,
+        pipeline: body.pipeline,
+        expected_leaf,
+    }.spawn(&mm, &ctx);
+
+    Ok((StatusCode::ACCEPTED, Json(SendRes { answer: String::new(), message_id: prompt_id, generation_id })))
+}
+
+/// Replaces a user prompt with an edited one. The edit starts a new branch
+/// from the same point and becomes the active one.
+pub async fn edit_msg(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((id, msg_id)): Path<(i64, i64)>,
+    Json(body): Json<SendReq>,
+) -> Result<(StatusCode, Json<SendRes>)> {
+    let conv = ConversationBmc::get_with_access(&ctx, &mm, id, Access::Write).await?;
+    let original = MessageBmc::get_in(&ctx, &mm, id, msg_id).await?;
+    if original.sender != "user" {
+        return Err(Error::ChatMessageInvalid("only user prompts can be edited".to_string()));
+    }
+    let model = body.model.or_else(|| conv.model.clone());
+    mm.llm.prompt_for(model.as_deref())?;
+    body.pipeline.resolve()?.config.validate(&mm.llm)?;
+
+    let prompt_id = MessageBmc::add(&ctx, &mm, id, original.parent_id, "user", &body.prompt, token::count(&body.prompt)).await?;
+    ConversationBmc::set_active_leaf(&ctx, &mm, id, prompt_id).await?;
+    publish_prompt(&ctx, &mm, id, prompt_id).await?;
+
+    let generation_id = PendingAnswer {
+        conv,
+        prompt_id,
+        prompt: body.prompt,
+        model,
+        agent: body.agent,
+        pipeline: body.pipeline,
+        expected_leaf: Some(prompt_id),
+    }.spawn(&mm, &ctx);
 
-    // 3) immediately return 202 Accepted (no answer payload)
-    Ok((StatusCode::ACCEPTED, Json(SendRes { answer: String::new() })))
+    Ok((StatusCode::ACCEPTED, Json(SendRes { answer: String::new(), message_id: prompt_id, generation_id })))
+}
+
+/// Switches to the branch through `message_id`, following the newest
+/// messages below it, and returns the new active path.
+pub async fn select_branch(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<SelectBranchReq>,
+) -> Result<Json<Vec<PathMessage>>> {
+    MessageBmc::get_in(&ctx, &mm, id, body.message_id).await?;
+
+    let tree = MessageBmc::tree(&ctx, &mm, id).await?;
+    ConversationBmc::set_active_leaf(&ctx, &mm, id, tree.newest_leaf(body.message_id)).await?;
+    Ok(Json(active_path(&ctx, &mm, id).await?))
+}
+
+pub async fn list_collaborators(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<Collaborator>>> {
+    Ok(Json(ConversationBmc::list_collaborators(&ctx, &mm, id).await?))
+}
+
+/// Shares the conversation with another user as viewer or editor; owner only.
+pub async fn set_collaborator(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((id, username)): Path<(i64, String)>,
+    Json(body): Json<CollaboratorReq>,
+) -> Result<Json<Vec<Collaborator>>> {
+    let user: User = UserBmc::first_by_username(&ctx, &mm, &username)
+        .await?
+        .ok_or(Error::EntityNotFound { entity: "user", id: 0 })?;
+    ConversationBmc::set_collaborator(&ctx, &mm, id, user.id, body.role).await?;
+    Ok(Json(ConversationBmc::list_collaborators(&ctx, &mm, id).await?))
+}
+
+pub async fn remove_collaborator(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((id, username)): Path<(i64, String)>,
+) -> Result<StatusCode> {
+    let user: User = UserBmc::first_by_username(&ctx, &mm, &username)
+        .await?
+        .ok_or(Error::EntityNotFound { entity: "user", id: 0 })?;
+    ConversationBmc::remove_collaborator(&ctx, &mm, id, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stops an answer being generated; what was generated so far is stored
+/// as the answer, with status `cancelled`.
+pub async fn delete_generation(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(generation_id): Path<Uuid>,
+) -> Result<Json<Message>> {
+    Ok(Json(cancel_generation(&ctx, &mm, generation_id).await?))
+}
+
+/// Thumbs up/down on an assistant answer; rating it again replaces the feedback.
+pub async fn rate_msg(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<FeedbackForCreate>,
+) -> Result<Json<Feedback>> {
+    Ok(Json(FeedbackBmc::upsert(&ctx, &mm, id, body).await?))
+}
+
+/// Downloads a conversation with citations and timestamps. Markdown and
+/// HTML show the active branch, JSON keeps every branch for re-import.
+pub async fn export_conv(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response> {
+    let export = ChatExportBmc::export(&ctx, &mm, id).await?;
+    let filename = format!("{id}-{}.{}", chat_export::slug(&export.title), params.format.extension());
+    let body = chat_export::render(&export, params.format)?;
+    Ok(attachment(params.format.content_type(), &filename, body.into_bytes()))
+}
+
+/// Zip archive of all the caller's own conversations, archived ones included.
+pub async fn export_all(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<ExportParams>,
+) -> Result<Response> {
+    let archive = ChatExportBmc::export_all(&ctx, &mm, params.format).await?;
+    Ok(attachment("application/zip", "conversations.zip", archive))
+}
+
+/// Recreates a conversation from its JSON export, owned by the caller.
+pub async fn import_conv(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Json(body): Json<ConversationExport>,
+) -> Result<(StatusCode, Json<Conversation>)> {
+    let id = ChatExportBmc::import(&ctx, &mm, body).await?;
+    Ok((StatusCode::CREATED, Json(ConversationBmc::get(&ctx, &mm, id).await?)))
+}
+
+/// Publishes a snapshot of the active branch under a public link; owner only.
+pub async fn create_share(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<ShareForCreate>,
+) -> Result<(StatusCode, Json<Share>)> {
+    Ok((StatusCode::CREATED, Json(ShareBmc::create(&ctx, &mm, id, body).await?)))
+}
+
+pub async fn list_shares(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<Share>>> {
+    Ok(Json(ShareBmc::list(&ctx, &mm, id).await?))
+}
+
+pub async fn revoke_share(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((id, share_id)): Path<(i64, i64)>,
+) -> Result<StatusCode> {
+    ShareBmc::revoke(&ctx, &mm, id, share_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn attachment(content_type: &'static str, filename: &str, body: Vec<u8>) -> Response {
+    let disposition = format!("attachment; filename=\"{filename}\"");
+    (
+        [(header::CONTENT_TYPE, content_type.to_string()), (header::CONTENT_DISPOSITION, disposition)],
+        body,
+    )
+        .into_response()
 }
 
 pub fn routes(mm: ModelManager) -> Router {
     Router::new()
         .route("/chat/conversations", post(create_conv).get(list_conv))
+        .route("/chat/conversations/:id", put(update_conv).delete(delete_conv))
+        .route("/chat/conversations/:id/collaborators", get(list_collaborators))
+        .route(
+            "/chat/conversations/:id/collaborators/:username",
+            put(set_collaborator).delete(remove_collaborator),
+        )
+        .route("/chat/conversations/:id/export", get(export_conv))
+        .route("/chat/conversations/:id/shares", post(create_share).get(list_shares))
+        .route("/chat/conversations/:id/shares/:share_id", delete(revoke_share))
+        .route("/chat/export", get(export_all))
+        .route("/chat/import", post(import_conv))
+        .route("/chat/search", get(search))
         .route(
             "/chat/conversations/:id/messages",
             get(list_msgs).post(send_msg),
         )
+        .route("/chat/conversations/:id/messages/:msg_id", put(edit_msg))
+        .route("/chat/conversations/:id/messages/:msg_id/regenerate", post(regenerate_msg))
+        .route("/chat/conversations/:id/branch", put(select_branch))
+        .route("/chat/generations/:id", delete(delete_generation))
+        .route("/chat/messages/:id/feedback", post(rate_msg))
         .with_state(mm)
 }
@@ -0,0 +1,99 @@
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    response::Response,
+    routing::get,
+    Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::{Ctx, Result};
+use crate::model::chat::{Access, ConversationBmc};
+use crate::model::chat_events::ChatEvent;
+use crate::model::manager::ModelManager;
+use crate::web::routes_chat::{cancel_generation, send_prompt, SendReq};
+
+/// What a client can ask for over the socket.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientRequest {
+    /// Same body as `POST /chat/conversations/:id/messages`.
+    Prompt(SendReq),
+    /// Same as `DELETE /chat/generations/:id`.
+    Cancel { generation_id: Uuid },
+}
+
+/// Live channel of a conversation, authenticated with the `auth-token`
+/// cookie like every other API call. Readers get the events, writers can
+/// also send prompts and cancel answers. The socket closes once the user
+/// loses access to the conversation.
+pub async fn chat_ws(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    ws: WebSocketUpgrade,
+) -> Result<Response> {
+    ConversationBmc::get(&ctx, &mm, id).await?;
+    Ok(ws.on_upgrade(move |socket| session(socket, mm, ctx, id)))
+}
+
+async fn session(socket: WebSocket, mm: ModelManager, ctx: Ctx, conv_id: i64) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut events = mm.chat_hub.subscribe(conv_id);
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(ChatEvent::AccessChanged) => match can_read(&ctx, &mm, conv_id).await {
+                    true => continue,
+                    false => break,
+                },
+                Ok(event) => event,
+                // an access change may have been among the skipped events
+                Err(RecvError::Lagged(skipped)) => match can_read(&ctx, &mm, conv_id).await {
+                    true => ChatEvent::Lagged { skipped },
+                    false => break,
+                },
+                Err(RecvError::Closed) => break,
+            },
+            request = receiver.next() => match request {
+                Some(Ok(WsMessage::Text(text))) => match handle(&ctx, &mm, conv_id, &text).await {
+                    Ok(()) => continue,
+                    Err(error) => ChatEvent::Error { error },
+                },
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        let Ok(json) = serde_json::to_string(&event) else { continue };
+        if sender.send(WsMessage::Text(json)).await.is_err() {
+            break;
+        }
+    }
+    let _ = sender.send(WsMessage::Close(None)).await;
+}
+
+async fn can_read(ctx: &Ctx, mm: &ModelManager, conv_id: i64) -> bool {
+    ConversationBmc::require(ctx, mm, conv_id, Access::Read).await.is_ok()
+}
+
+/// Runs a client request; the outcome reaches the client as events.
+async fn handle(ctx: &Ctx, mm: &ModelManager, conv_id: i64, text: &str) -> core::result::Result<(), String> {
+    let request: ClientRequest = serde_json::from_str(text).map_err(|e| format!("invalid request: {e}"))?;
+    let result = match request {
+        ClientRequest::Prompt(body) => send_prompt(ctx, mm, conv_id, body).await.map(|_| ()),
+        ClientRequest::Cancel { generation_id } => cancel_generation(ctx, mm, generation_id).await.map(|_| ()),
+    };
+    result.map_err(|e| e.client_status_and_error().1.as_ref().to_string())
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/chat/conversations/:id/ws", get(chat_ws))
+        .with_state(mm)
+}
@@ -0,0 +1,161 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::spawn;
+use tracing::{error, Instrument};
+
+use crate::{Ctx, Result};
+use crate::model::eval::*;
+use crate::model::manager::ModelManager;
+use crate::model::pipeline::{PipelineParams, PipelineSelection};
+
+#[derive(Debug, Deserialize)]
+struct NewDataset {
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    cases: Vec<EvalCaseForCreate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewCases {
+    cases: Vec<EvalCaseForCreate>,
+}
+
+#[derive(Debug, Serialize)]
+struct DatasetWithCases {
+    #[serde(flatten)]
+    dataset: EvalDataset,
+    cases: Vec<EvalCase>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewRun {
+    label: Option<String>,
+    #[serde(flatten)]
+    config: EvalRunConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct RunsParams {
+    dataset_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RunWithResults {
+    #[serde(flatten)]
+    run: EvalRun,
+    results: Vec<EvalResult>,
+}
+
+async fn create_dataset(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Json(body): Json<NewDataset>,
+) -> Result<Json<DatasetWithCases>> {
+    println!("->> {:<12} - create_eval_dataset", "HANDLER");
+    let dataset = EvalBmc::create_dataset(&ctx, &mm, &body.name, body.description.as_deref(), body.cases).await?;
+    let cases = EvalBmc::list_cases(&ctx, &mm, dataset.id).await?;
+    Ok(Json(DatasetWithCases { dataset, cases }))
+}
+
+async fn list_datasets(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<EvalDataset>>> {
+    println!("->> {:<12} - list_eval_datasets", "HANDLER");
+    Ok(Json(EvalBmc::list_datasets(&ctx, &mm).await?))
+}
+
+async fn get_dataset(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<DatasetWithCases>> {
+    println!("->> {:<12} - get_eval_dataset", "HANDLER");
+    let dataset = EvalBmc::get_dataset(&ctx, &mm, id).await?;
+    let cases = EvalBmc::list_cases(&ctx, &mm, id).await?;
+    Ok(Json(DatasetWithCases { dataset, cases }))
+}
+
+async fn add_cases(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<NewCases>,
+) -> Result<Json<Vec<EvalCase>>> {
+    println!("->> {:<12} - add_eval_cases", "HANDLER");
+    Ok(Json(EvalBmc::add_cases(&ctx, &mm, id, body.cases).await?))
+}
+
+/// Starts a run in the background; poll `GET /evals/runs/:id` for the results.
+async fn start_run(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(dataset_id): Path<i64>,
+    Json(mut body): Json<NewRun>,
+) -> Result<(StatusCode, Json<EvalRun>)> {
+    println!("->> {:<12} - start_eval_run", "HANDLER");
+
+    // reject bad settings before anything is persisted
+    mm.llm.prompt_for(body.config.model.as_deref())?;
+    let resolved = body.config.pipeline.resolve()?;
+    resolved.config.validate(&mm.llm)?;
+
+    // store the resolved config so the run stays comparable when presets change,
+    // and measure the pipeline rather than the answer cache
+    body.config.pipeline = PipelineParams {
+        pipeline: Some(PipelineSelection::Config(Box::new(resolved.config))),
+        cache: Some(false),
+        ..Default::default()
+    };
+    let run = EvalBmc::create_run(&ctx, &mm, dataset_id, body.label.as_deref(), body.config).await?;
+
+    let mm2 = mm.clone();
+    let ctx2 = ctx.clone();
+    let run_id = run.id;
+    spawn(async move {
+        if let Err(e) = EvalBmc::execute_run(&ctx2, &mm2, run_id).await {
+            error!("eval run {run_id} failed: {e}");
+            let _ = EvalBmc::fail_run(&ctx2, &mm2, run_id).await;
+        }
+    }.in_current_span());
+
+    Ok((StatusCode::ACCEPTED, Json(run)))
+}
+
+/// Runs with their aggregated metrics, newest first, for side by side comparison.
+async fn list_runs(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<RunsParams>,
+) -> Result<Json<Vec<EvalRun>>> {
+    println!("->> {:<12} - list_eval_runs", "HANDLER");
+    Ok(Json(EvalBmc::list_runs(&ctx, &mm, params.dataset_id).await?))
+}
+
+async fn get_run(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<RunWithResults>> {
+    println!("->> {:<12} - get_eval_run", "HANDLER");
+    let run = EvalBmc::get_run(&ctx, &mm, id).await?;
+    let results = EvalBmc::list_results(&ctx, &mm, id).await?;
+    Ok(Json(RunWithResults { run, results }))
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/evals/datasets", post(create_dataset).get(list_datasets))
+        .route("/evals/datasets/:id", get(get_dataset))
+        .route("/evals/datasets/:id/cases", post(add_cases))
+        .route("/evals/datasets/:id/runs", post(start_run))
+        .route("/evals/runs", get(list_runs))
+        .route("/evals/runs/:id", get(get_run))
+        .with_state(mm)
+}
@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{Ctx, Result};
+use crate::model::eval::EvalCase;
+use crate::model::feedback::{FeedbackBmc, FeedbackFilter, FeedbackReport, FeedbackSummary};
+use crate::model::manager::ModelManager;
+
+#[derive(Debug, Deserialize)]
+struct SummaryParams {
+    since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportRequest {
+    dataset_id: i64,
+    #[serde(flatten)]
+    filter: FeedbackFilter,
+}
+
+/// Rated answers (thumbs down unless `rating=up`), with question, config and chunks.
+async fn list_reports(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(filter): Query<FeedbackFilter>,
+) -> Result<Json<Vec<FeedbackReport>>> {
+    println!("->> {:<12} - list_feedback_reports", "HANDLER");
+    Ok(Json(FeedbackBmc::list_reports(&ctx, &mm, &filter).await?))
+}
+
+async fn summary(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<SummaryParams>,
+) -> Result<Json<Vec<FeedbackSummary>>> {
+    println!("->> {:<12} - feedback_summary", "HANDLER");
+    Ok(Json(FeedbackBmc::summary(&ctx, &mm, params.since).await?))
+}
+
+/// Turns the reported questions into cases of an eval dataset.
+async fn export(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Json(body): Json<ExportRequest>,
+) -> Result<Json<Vec<EvalCase>>> {
+    println!("->> {:<12} - export_feedback", "HANDLER");
+    Ok(Json(FeedbackBmc::export_to_dataset(&ctx, &mm, body.dataset_id, &body.filter).await?))
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/feedback", get(list_reports))
+        .route("/feedback/summary", get(summary))
+        .route("/feedback/export", post(export))
+        .with_state(mm)
+}
@@ -10,15 +10,26 @@ use tracing::info;
 
 use crate::{Ctx, Result};
 use crate::model::manager::ModelManager;
+use crate::model::pipeline::PipelineParams;
 
 #[derive(Debug, Deserialize)]
 pub struct FineTuneRequest {
     pub prompt: String,
+    pub model: Option<String>,
+    /// `pipeline` (preset name or inline config) plus retrieval overrides:
+    /// `mode`, `top_k`, `vectors`, `dense_weight`, `sparse_weight`, `rerank`, `rerank_candidates`, `top_n`.
+    #[serde(flatten)]
+    pub pipeline: PipelineParams,
 }
 
 #[derive(Debug, Serialize)]
 pub struct FineTuneResponse {
     pub response: String,
+    /// Share of the answer's claims supported by the retrieved documents; set when the groundedness check ran.
+    pub confidence: Option<f64>,
+    pub grounded: Option<bool>,
+    /// Nothing relevant was retrieved and `response` is the no-answer message.
+    pub fallback: bool,
 }
 
 #[tracing::instrument(skip_all, name = "fine_tune_handler")]
@@ -29,12 +40,15 @@ pub async fn fine_tune_handler(
 ) -> Result<Json<FineTuneResponse>> {
     info!("Received fine-tune request: {:?}", payload.prompt);
 
-    let refined_answer = mm.fine_tune_prompt(&ctx, &payload.prompt).await?;
+    let refined_answer = mm.fine_tune_prompt(&ctx, &payload.prompt, payload.model.as_deref(), &payload.pipeline).await?;
 
-    info!("Refined answer: {:?}", refined_answer);
+    info!("Refined answer: {:?}", refined_answer.answer);
 
     Ok(Json(FineTuneResponse {
-        response: refined_answer,
+        response: refined_answer.answer,
+        confidence: refined_answer.confidence,
+        grounded: refined_answer.grounded,
+        fallback: refined_answer.fallback,
     }))
 }
 
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use swiftide::traits::SimplePrompt;
+
+use crate::{Ctx, Error, Result};
+use crate::model::manager::ModelManager;
+use crate::model::prompt::{self, PromptBmc, PromptTemplate, PromptTemplateForCreate, PROMPT_STAGES};
+
+#[derive(Debug, Serialize)]
+struct PromptStage {
+    name: &'static str,
+    description: &'static str,
+    /// Active stored version; the built-in prompt is used when absent.
+    version: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewRequest {
+    /// Stored template to preview; `template` is used when absent.
+    name: Option<String>,
+    /// Latest version when absent.
+    version: Option<i32>,
+    template: Option<String>,
+    #[serde(default)]
+    variables: HashMap<String, serde_json::Value>,
+    /// Also send the rendered prompt to the model.
+    #[serde(default)]
+    run: bool,
+    model: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PreviewResponse {
+    rendered: String,
+    variables: Vec<String>,
+    output: Option<String>,
+    latency_ms: Option<u128>,
+}
+
+async fn list_prompts(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<PromptTemplate>>> {
+    println!("->> {:<12} - list_prompts", "HANDLER");
+    Ok(Json(PromptBmc::list_latest(&ctx, &mm).await?))
+}
+
+/// Replaceable prompts and whether a stored template overrides them.
+async fn list_stages(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<PromptStage>>> {
+    println!("->> {:<12} - list_prompt_stages", "HANDLER");
+    let stored = PromptBmc::list_latest(&ctx, &mm).await?;
+    let stages = PROMPT_STAGES
+        .iter()
+        .map(|(name, description)| PromptStage {
+            name,
+            description,
+            version: stored.iter().find(|t| t.name == *name).map(|t| t.version),
+        })
+        .collect();
+    Ok(Json(stages))
+}
+
+async fn list_versions(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<PromptTemplate>>> {
+    println!("->> {:<12} - list_prompt_versions", "HANDLER");
+    Ok(Json(PromptBmc::list_versions(&ctx, &mm, &name).await?))
+}
+
+/// Stores a new version, which becomes active for the next pipeline run.
+async fn create_version(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(name): Path<String>,
+    Json(body): Json<PromptTemplateForCreate>,
+) -> Result<(StatusCode, Json<PromptTemplate>)> {
+    println!("->> {:<12} - create_prompt_version", "HANDLER");
+    let template = PromptBmc::create_version(&ctx, &mm, &name, body).await?;
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+async fn delete_prompt(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    println!("->> {:<12} - delete_prompt", "HANDLER");
+    PromptBmc::delete(&ctx, &mm, &name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_version(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((name, version)): Path<(String, i32)>,
+) -> Result<Json<PromptTemplate>> {
+    println!("->> {:<12} - get_prompt_version", "HANDLER");
+    Ok(Json(PromptBmc::get_version(&ctx, &mm, &name, version).await?))
+}
+
+async fn restore_version(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path((name, version)): Path<(String, i32)>,
+) -> Result<(StatusCode, Json<PromptTemplate>)> {
+    println!("->> {:<12} - restore_prompt_version", "HANDLER");
+    let template = PromptBmc::restore_version(&ctx, &mm, &name, version).await?;
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+/// Renders a stored or inline template with the given variables and
+/// optionally runs it against the prompt model.
+async fn preview(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Json(body): Json<PreviewRequest>,
+) -> Result<Json<PreviewResponse>> {
+    println!("->> {:<12} - preview_prompt", "HANDLER");
+
+    let template = match (&body.name, body.template) {
+        (Some(name), _) => match body.version {
+            Some(version) => PromptBmc::get_version(&ctx, &mm, name, version).await?.template,
+            None => PromptBmc::get_latest(&ctx, &mm, name).await?.template,
+        },
+        (None, Some(template)) => template,
+        (None, None) => return Err(Error::PromptTemplateInvalid("name or template is required".to_string())),
+    };
+    let rendered = prompt::render(&template, &body.variables).await?;
+
+    let (output, latency_ms) = match body.run {
+        true => {
+            let llm = mm.llm.prompt_for(body.model.as_deref())?;
+            let start = Instant::now();
+            let output = llm
+                .prompt(prompt::to_prompt(&template, &body.variables))
+                .await
+                .map_err(|e| Error::LlmError(e.to_string()))?;
+            (Some(output), Some(start.elapsed().as_millis()))
+        }
+        false => (None, None),
+    };
+
+    Ok(Json(PreviewResponse {
+        rendered,
+        variables: prompt::template_variables(&template),
+        output,
+        latency_ms,
+    }))
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/prompts", get(list_prompts))
+        .route("/prompts/stages", get(list_stages))
+        .route("/prompts/preview", post(preview))
+        .route("/prompts/:name", get(list_versions).post(create_version).delete(delete_prompt))
+        .route("/prompts/:name/versions/:version", get(get_version))
+        .route("/prompts/:name/versions/:version/restore", post(restore_version))
+        .with_state(mm)
+}
@@ -10,15 +10,24 @@ use tracing::info;
 
 use crate::{Ctx, Result};
 use crate::model::manager::ModelManager;
+use crate::model::pipeline::PipelineParams;
+use crate::model::retrieval::RetrievedDocument;
 
 #[derive(Debug, Deserialize)]
 pub struct QueryRequest {
     pub prompt: String,
+    pub model: Option<String>,
+    /// `pipeline` (preset name or inline config) plus retrieval overrides:
+    /// `mode`, `top_k`, `vectors`, `dense_weight`, `sparse_weight`, `rerank`, `rerank_candidates`, `top_n`.
+    #[serde(flatten)]
+    pub pipeline: PipelineParams,
 }
 
 #[derive(Debug, Serialize)]
 pub struct QueryResponse {
+    /// Titles of `results`, kept for existing clients.
     pub retrieved_contexts: Vec<String>,
+    pub results: Vec<RetrievedDocument>,
 }
 
 #[tracing::instrument(skip_all, name = "query_data_handler")]
@@ -29,12 +38,13 @@ pub async fn query_data_handler(
 ) -> Result<Json<QueryResponse>> {
     info!("Received query: {:?}", payload.prompt);
 
-    let retrieved_docs = mm.query_data(&ctx, &payload.prompt).await?;
+    let retrieved_docs = mm.query_data(&ctx, &payload.prompt, payload.model.as_deref(), &payload.pipeline).await?;
 
     info!("Retrieved docs: {:?}", retrieved_docs);
 
     Ok(Json(QueryResponse {
-        retrieved_contexts: retrieved_docs,
+        retrieved_contexts: retrieved_docs.iter().map(|d| d.title.clone()).collect(),
+        results: retrieved_docs,
     }))
 }
 
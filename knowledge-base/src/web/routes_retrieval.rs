@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
+use axum::{
+    extract::State,
+    Json,
+    routing::post,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+use crate::model::eval::{recall_at_k, reciprocal_rank, unique_doc_ids};
+use crate::model::manager::ModelManager;
+use crate::model::retrieval::{RetrievalParams, RetrievedDocument};
+
+const MAX_COMPARED_STRATEGIES: usize = 8;
+
+#[derive(Debug, Deserialize)]
+pub struct NamedStrategy {
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub params: RetrievalParams,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareRequest {
+    pub prompt: String,
+    pub strategies: Vec<NamedStrategy>,
+    /// When given, each strategy is scored against these document ids.
+    pub expected_doc_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StrategyResult {
+    pub name: String,
+    pub latency_ms: u128,
+    /// Share of this strategy's documents also returned by the first strategy.
+    pub overlap_with_first: f64,
+    pub recall: Option<f64>,
+    pub reciprocal_rank: Option<f64>,
+    pub documents: Vec<RetrievedDocument>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompareResponse {
+    pub prompt: String,
+    pub results: Vec<StrategyResult>,
+}
+
+/// Runs the same prompt through several retrieval strategies, e.g.
+/// `{"vectors": ["combined"]}` against `{"vectors": ["questions", "keywords"]}`.
+#[tracing::instrument(skip_all, name = "compare_strategies_handler")]
+pub async fn compare_strategies_handler(
+    State(mm): State<ModelManager>,
+    Json(payload): Json<CompareRequest>,
+) -> Result<Json<CompareResponse>> {
+    println!("->> {:<12} - compare_strategies", "HANDLER");
+
+    if payload.strategies.is_empty() || payload.strategies.len() > MAX_COMPARED_STRATEGIES {
+        return Err(Error::RetrievalParamsInvalid);
+    }
+
+    let mut results: Vec<StrategyResult> = Vec::with_capacity(payload.strategies.len());
+    let mut baseline: Option<HashSet<String>> = None;
+    for (idx, strategy) in payload.strategies.into_iter().enumerate() {
+        let start = Instant::now();
+        let documents = mm.retrieve(&payload.prompt, &strategy.params).await?;
+        let latency_ms = start.elapsed().as_millis();
+
+        let keys: Vec<String> = documents.iter().map(document_key).collect();
+        let baseline = baseline.get_or_insert_with(|| keys.iter().cloned().collect());
+        let overlap_with_first = match keys.len() {
+            0 => 0.0,
+            n => keys.iter().filter(|k| baseline.contains(*k)).count() as f64 / n as f64,
+        };
+
+        let doc_ids = unique_doc_ids(documents.iter().filter_map(|d| d.doc_id.clone()));
+        let expected = payload.expected_doc_ids.as_deref();
+        results.push(StrategyResult {
+            name: strategy.name.unwrap_or_else(|| format!("strategy_{}", idx + 1)),
+            latency_ms,
+            overlap_with_first,
+            recall: expected.map(|expected| recall_at_k(&doc_ids, expected, doc_ids.len())),
+            reciprocal_rank: expected.map(|expected| reciprocal_rank(&doc_ids, expected)),
+            documents,
+        });
+    }
+
+    Ok(Json(CompareResponse {
+        prompt: payload.prompt,
+        results,
+    }))
+}
+
+/// Chunks have no stable id in the API; document id plus title is close enough.
+fn document_key(document: &RetrievedDocument) -> String {
+    format!("{}:{}", document.doc_id.as_deref().unwrap_or_default(), document.title)
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/retrieval/compare", post(compare_strategies_handler))
+        .with_state(mm)
+}
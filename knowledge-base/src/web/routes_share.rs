@@ -0,0 +1,48 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+
+use crate::Result;
+use crate::model::chat_export::{self, ExportFormat};
+use crate::model::chat_share::ShareBmc;
+use crate::model::manager::ModelManager;
+
+#[derive(Debug, Deserialize)]
+struct SharedParams {
+    /// HTML page when absent; `md` and `json` for the raw snapshot.
+    format: Option<ExportFormat>,
+}
+
+/// Public, read-only view of a shared conversation. No login required.
+async fn view_shared(
+    State(mm): State<ModelManager>,
+    Path(token): Path<String>,
+    Query(params): Query<SharedParams>,
+) -> Result<Response> {
+    println!("->> {:<12} - view_shared_conversation", "HANDLER");
+    let format = params.format.unwrap_or(ExportFormat::Html);
+    let snapshot = ShareBmc::get_public(&mm, &token).await?;
+    let body = chat_export::render(&snapshot, format)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::CACHE_CONTROL, "no-store"),
+            // keep the token out of the Referer of links followed from the page
+            (header::REFERRER_POLICY, "no-referrer"),
+            (header::HeaderName::from_static("x-robots-tag"), "noindex"),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/shared/:token", get(view_shared))
+        .with_state(mm)
+}
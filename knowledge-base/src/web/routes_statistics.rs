@@ -1,24 +1,98 @@
 use axum::{
-    extract::State,
+    extract::{Query, State},
     Json, Router,
     routing::get,
 };
+use chrono::{DateTime, Duration, Utc};
 use serde::{Serialize, Deserialize};
 use sqlx::Row;
+use std::path::Path;
+use qdrant_client::qdrant::CountPointsBuilder;
 
 use crate::{
+    config::config,
     ctx::Ctx,
     error::{Error, Result},
-    model::manager::ModelManager,
+    model::manager::{ModelManager, QDRANT_COLLECTION},
 };
 
+const TOP_USERS_LIMIT: i64 = 10;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl Bucket {
+    /// Unit understood by Postgres `date_trunc`.
+    fn as_pg_unit(&self) -> &'static str {
+        match self {
+            Bucket::Hour => "hour",
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatisticsParams {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub bucket: Bucket,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DayCount {
     pub day: String,    // e.g. "2025-05-18"
     pub count: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
+pub struct BucketCount {
+    pub bucket: DateTime<Utc>,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PipelineStats {
+    pub pipeline: String,
+    pub runs: i64,
+    pub errors: i64,
+    pub error_rate: f64,
+    pub avg_duration_ms: f64,
+    pub p50_duration_ms: f64,
+    pub p95_duration_ms: f64,
+    pub p99_duration_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserCount {
+    pub user_id: i64,
+    pub username: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestionBucket {
+    pub bucket: DateTime<Utc>,
+    pub documents: i64,
+    pub failed: i64,
+    pub avg_duration_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageUsage {
+    pub uploads_dir_bytes: u64,
+    pub uploads_dir_files: u64,
+    pub qdrant_points: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
 pub struct StatisticsResponse {
     pub total_users: i64,
     pub total_tasks: i64,
@@ -35,12 +109,31 @@ pub struct StatisticsResponse {
     pub avg_docs_per_user: f64,
 
     pub messages_last_7_days: Vec<DayCount>,
+
+    // -- Range-scoped (from/to/bucket)
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub messages_per_bucket: Vec<BucketCount>,
+    pub pipelines: Vec<PipelineStats>,
+    pub top_users_by_messages: Vec<UserCount>,
+    pub top_users_by_documents: Vec<UserCount>,
+    pub ingestion: Vec<IngestionBucket>,
+
+    pub storage: StorageUsage,
 }
 
 pub async fn get_statistics(
     State(mm): State<ModelManager>,
     _ctx: Ctx,
+    Query(params): Query<StatisticsParams>,
 ) -> Result<Json<StatisticsResponse>> {
+    let to = params.to.unwrap_or_else(Utc::now);
+    let from = params.from.unwrap_or(to - Duration::days(7));
+    if from >= to {
+        return Err(Error::StatisticsRangeInvalid("`from` must be before `to`".into()));
+    }
+    let bucket = params.bucket.as_pg_unit();
+
     // 1) core counts
     let total_users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM \"user\"")
         .fetch_one(mm.db()).await?;
@@ -118,6 +211,14 @@ pub async fn get_statistics(
         }
     }).collect();
 
+    // 5) range-scoped statistics
+    let messages_per_bucket = messages_per_bucket(&mm, from, to, bucket).await?;
+    let pipelines = pipeline_stats(&mm, from, to).await?;
+    let top_users_by_messages = top_users_by_messages(&mm, from, to).await?;
+    let top_users_by_documents = top_users_by_documents(&mm, from, to).await?;
+    let ingestion = ingestion_throughput(&mm, from, to, bucket).await?;
+    let storage = storage_usage(&mm).await;
+
     Ok(Json(StatisticsResponse {
         total_users,
         total_tasks,
@@ -133,9 +234,213 @@ pub async fn get_statistics(
         avg_docs_per_user: avg_docs_per_user.unwrap_or(0.0),
 
         messages_last_7_days,
+
+        from,
+        to,
+        messages_per_bucket,
+        pipelines,
+        top_users_by_messages,
+        top_users_by_documents,
+        ingestion,
+
+        storage,
     }))
 }
 
+async fn messages_per_bucket(
+    mm: &ModelManager,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket: &str,
+) -> Result<Vec<BucketCount>> {
+    let rows = sqlx::query(
+        "SELECT date_trunc($1, created_at) AS bucket, COUNT(*) AS count
+         FROM message
+         WHERE created_at >= $2 AND created_at < $3
+         GROUP BY bucket
+         ORDER BY bucket"
+    )
+        .bind(bucket)
+        .bind(from)
+        .bind(to)
+        .fetch_all(mm.db()).await?;
+
+    Ok(rows.into_iter().map(|r| BucketCount {
+        bucket: r.get("bucket"),
+        count: r.get("count"),
+    }).collect())
+}
+
+async fn pipeline_stats(
+    mm: &ModelManager,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<PipelineStats>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT pipeline,
+               COUNT(*) AS runs,
+               COUNT(*) FILTER (WHERE status = 'error') AS errors,
+               AVG(duration_ms)::double precision AS avg_ms,
+               percentile_cont(0.50) WITHIN GROUP (ORDER BY duration_ms) AS p50,
+               percentile_cont(0.95) WITHIN GROUP (ORDER BY duration_ms) AS p95,
+               percentile_cont(0.99) WITHIN GROUP (ORDER BY duration_ms) AS p99
+        FROM pipeline_log
+        WHERE created_at >= $1 AND created_at < $2
+        GROUP BY pipeline
+        ORDER BY pipeline
+        "#
+    )
+        .bind(from)
+        .bind(to)
+        .fetch_all(mm.db()).await?;
+
+    Ok(rows.into_iter().map(|r| {
+        let runs: i64 = r.get("runs");
+        let errors: i64 = r.get("errors");
+        PipelineStats {
+            pipeline: r.get("pipeline"),
+            runs,
+            errors,
+            error_rate: if runs > 0 { errors as f64 / runs as f64 } else { 0.0 },
+            avg_duration_ms: r.get::<Option<f64>, _>("avg_ms").unwrap_or(0.0),
+            p50_duration_ms: r.get::<Option<f64>, _>("p50").unwrap_or(0.0),
+            p95_duration_ms: r.get::<Option<f64>, _>("p95").unwrap_or(0.0),
+            p99_duration_ms: r.get::<Option<f64>, _>("p99").unwrap_or(0.0),
+        }
+    }).collect())
+}
+
+async fn top_users_by_messages(
+    mm: &ModelManager,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<UserCount>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT u.id AS user_id, u.username, COUNT(m.id) AS count
+        FROM message m
+        JOIN conversation c ON c.id = m.conversation_id
+        JOIN "user" u ON u.id = c.owner_id
+        WHERE m.sender = 'user' AND m.created_at >= $1 AND m.created_at < $2
+        GROUP BY u.id, u.username
+        ORDER BY count DESC
+        LIMIT $3
+        "#
+    )
+        .bind(from)
+        .bind(to)
+        .bind(TOP_USERS_LIMIT)
+        .fetch_all(mm.db()).await?;
+
+    Ok(rows.into_iter().map(user_count_from_row).collect())
+}
+
+async fn top_users_by_documents(
+    mm: &ModelManager,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<UserCount>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT u.id AS user_id, u.username, COUNT(d.id) AS count
+        FROM document d
+        JOIN "user" u ON u.id = d.uploaded_by
+        WHERE d.created_at >= $1 AND d.created_at < $2
+        GROUP BY u.id, u.username
+        ORDER BY count DESC
+        LIMIT $3
+        "#
+    )
+        .bind(from)
+        .bind(to)
+        .bind(TOP_USERS_LIMIT)
+        .fetch_all(mm.db()).await?;
+
+    Ok(rows.into_iter().map(user_count_from_row).collect())
+}
+
+fn user_count_from_row(r: sqlx::postgres::PgRow) -> UserCount {
+    UserCount {
+        user_id: r.get("user_id"),
+        username: r.get("username"),
+        count: r.get("count"),
+    }
+}
+
+async fn ingestion_throughput(
+    mm: &ModelManager,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket: &str,
+) -> Result<Vec<IngestionBucket>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT date_trunc($1, created_at) AS bucket,
+               COUNT(*) FILTER (WHERE status = 'ok') AS documents,
+               COUNT(*) FILTER (WHERE status = 'error') AS failed,
+               AVG(duration_ms)::double precision AS avg_ms
+        FROM pipeline_log
+        WHERE pipeline = 'ingest_document' AND created_at >= $2 AND created_at < $3
+        GROUP BY bucket
+        ORDER BY bucket
+        "#
+    )
+        .bind(bucket)
+        .bind(from)
+        .bind(to)
+        .fetch_all(mm.db()).await?;
+
+    Ok(rows.into_iter().map(|r| IngestionBucket {
+        bucket: r.get("bucket"),
+        documents: r.get("documents"),
+        failed: r.get("failed"),
+        avg_duration_ms: r.get::<Option<f64>, _>("avg_ms").unwrap_or(0.0),
+    }).collect())
+}
+
+/// Storage figures are informative only, so failures degrade to zero/`None`
+/// instead of failing the whole statistics call.
+async fn storage_usage(mm: &ModelManager) -> StorageUsage {
+    let upload_dir = config().UPLOAD_DIR.clone();
+    let (uploads_dir_bytes, uploads_dir_files) =
+        tokio::task::spawn_blocking(move || dir_size(Path::new(&upload_dir)))
+            .await
+            .unwrap_or((0, 0));
+
+    let qdrant_points = mm.qdrant
+        .client()
+        .count(CountPointsBuilder::new(QDRANT_COLLECTION).exact(true))
+        .await
+        .ok()
+        .and_then(|res| res.result)
+        .map(|res| res.count);
+
+    StorageUsage {
+        uploads_dir_bytes,
+        uploads_dir_files,
+        qdrant_points,
+    }
+}
+
+/// Returns (total bytes, file count) for everything below `path`.
+fn dir_size(path: &Path) -> (u64, u64) {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return (0, 0);
+    };
+
+    entries.flatten().fold((0, 0), |(bytes, files), entry| {
+        match entry.metadata() {
+            Ok(meta) if meta.is_dir() => {
+                let (b, f) = dir_size(&entry.path());
+                (bytes + b, files + f)
+            }
+            Ok(meta) => (bytes + meta.len(), files + 1),
+            Err(_) => (bytes, files),
+        }
+    })
+}
+
 pub fn routes(mm: ModelManager) -> Router {
     Router::new()
         .route("/statistics", get(get_statistics))
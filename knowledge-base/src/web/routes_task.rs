@@ -1,13 +1,28 @@
 
 use axum::extract::{Path, State};
+use axum::http::StatusCode;
 use axum::{Json, Router};
 use axum::routing::{delete, get, post, put};
+use serde::Deserialize;
 use tracing::info;
 use crate::ctx::Ctx;
+use crate::model::action_items::{ActionItemBmc, ActionItems, ProposedTask};
 use crate::model::task::{Task, TaskForCreate, TaskForUpdate, TaskBmc, TaskForCreateInternal};
 use crate::model::manager::ModelManager;
 
 use crate::Result;
+
+#[derive(Debug, Deserialize)]
+struct ExtractReq {
+    /// Prompt model to extract with; the server default (or the conversation's) when absent.
+    model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcceptReq {
+    tasks: Vec<ProposedTask>,
+}
+
 #[tracing::instrument]
 async fn create_task(
     State(mm): State<ModelManager>,
@@ -18,6 +33,7 @@ async fn create_task(
     let task_internal = TaskForCreateInternal {
         title: task_fc.title,
         created_by: ctx.user_id(),
+        ..Default::default()
     };
     let task = TaskBmc::create(&ctx, &mm, task_internal).await?;
     info!("Task created: {:?}", task);
@@ -29,7 +45,7 @@ async fn list_tasks(
     ctx:Ctx,
 ) -> Result<Json<Vec<Task>>> {
     println!("->> {:<12} - list_tasks", "HANDLER");
-    let tasks = TaskBmc::list(&ctx, &mc).await?;
+    let tasks = TaskBmc::list_for_user(&ctx, &mc).await?;
     info!("Tasks listed");
     Ok(Json(tasks))
 }
@@ -68,6 +84,45 @@ async fn update_task(
     Ok(Json(task))
 }
 
+/// Proposes action items found in a document; nothing is stored.
+#[tracing::instrument]
+async fn extract_from_document(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<ExtractReq>,
+) -> Result<Json<ActionItems>> {
+    println!("->> {:<12} - extract_from_document", "HANDLER");
+    let items = ActionItemBmc::from_document(&ctx, &mm, id, body.model.as_deref()).await?;
+    info!("Action items proposed: {}", items.proposals.len());
+    Ok(Json(items))
+}
+/// Proposes action items found in a conversation; nothing is stored.
+#[tracing::instrument]
+async fn extract_from_conversation(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Json(body): Json<ExtractReq>,
+) -> Result<Json<ActionItems>> {
+    println!("->> {:<12} - extract_from_conversation", "HANDLER");
+    let items = ActionItemBmc::from_conversation(&ctx, &mm, id, body.model.as_deref()).await?;
+    info!("Action items proposed: {}", items.proposals.len());
+    Ok(Json(items))
+}
+/// Stores the proposals the user kept, as is or edited, as tasks.
+#[tracing::instrument]
+async fn accept_action_items(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Json(body): Json<AcceptReq>,
+) -> Result<(StatusCode, Json<Vec<Task>>)> {
+    println!("->> {:<12} - accept_action_items", "HANDLER");
+    let tasks = ActionItemBmc::accept(&ctx, &mm, body.tasks).await?;
+    info!("Action items accepted: {}", tasks.len());
+    Ok((StatusCode::CREATED, Json(tasks)))
+}
+
 pub fn routes(mm:ModelManager)->Router{
     Router::new()
         .route("/tasks",post(create_task))
@@ -75,5 +130,8 @@ pub fn routes(mm:ModelManager)->Router{
         .route("/tasks/:id",delete(delete_task))
         .route("/tasks/:id",get(get_task))
         .route("/tasks/:id",put(update_task))
+        .route("/tasks/extract/documents/:id",post(extract_from_document))
+        .route("/tasks/extract/conversations/:id",post(extract_from_conversation))
+        .route("/tasks/accept",post(accept_action_items))
         .with_state(mm)
 }
@@ -775,6 +775,245 @@ async fn quick_dev() -> Result<()> {
         .await?;
     print_response(res, "GET /admin/statistics (User 3 admin)").await?;
 
+    info!("Accessing /admin/statistics with an hourly range as user3 ...");
+    let res = user3_client
+        .get("http://localhost:8000/admin/statistics")
+        .query(&[
+            ("from", "2025-01-01T00:00:00Z"),
+            ("to", "2025-01-02T00:00:00Z"),
+            ("bucket", "hour"),
+        ])
+        .send()
+        .await?;
+    let stats = print_response(res, "GET /admin/statistics?bucket=hour (User 3 admin)").await?;
+    assert!(stats["pipelines"].is_array(), "per-pipeline breakdown missing");
+
+    info!("Accessing /admin/statistics with a reversed range as user3 ...");
+    let res = user3_client
+        .get("http://localhost:8000/admin/statistics")
+        .query(&[("from", "2025-01-02T00:00:00Z"), ("to", "2025-01-01T00:00:00Z")])
+        .send()
+        .await?;
+    assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST, "a reversed range is a client error");
+
+    info!("Comparing retrieval strategies as user3 ...");
+    let res = user3_client
+        .post("http://localhost:8000/admin/retrieval/compare")
+        .json(&json!({
+            "prompt": "What is the knowledge base about?",
+            "strategies": [
+                { "name": "combined", "vectors": ["combined"] },
+                { "name": "questions+keywords", "vectors": ["questions", "keywords"] }
+            ]
+        }))
+        .send()
+        .await?;
+    let compare = print_response(res, "POST /admin/retrieval/compare (User 3 admin)").await?;
+    assert_eq!(compare["results"].as_array().map(|r| r.len()), Some(2));
+
+    info!("Checking a score threshold with fused hybrid retrieval as user3 ...");
+    let res = user3_client
+        .post("http://localhost:8000/admin/retrieval/compare")
+        .json(&json!({
+            "prompt": "What is the knowledge base about?",
+            "strategies": [
+                { "name": "hybrid", "mode": "hybrid", "vectors": ["questions", "keywords"] },
+                // above any RRF score, so it only passes on the raw similarity
+                { "name": "hybrid-min-score", "mode": "hybrid", "vectors": ["questions", "keywords"], "min_score": 0.05 },
+                // above any cosine similarity: sparse scores and title distances must not pass it
+                { "name": "hybrid-above-cosine", "mode": "hybrid", "vectors": ["questions", "title"], "min_score": 1.01, "rerank": false }
+            ]
+        }))
+        .send()
+        .await?;
+    if res.status().is_success() {
+        let hybrid = print_response(res, "POST /admin/retrieval/compare hybrid (User 3 admin)").await?;
+        let found = |i: usize| hybrid["results"][i]["documents"].as_array().map_or(0, |d| d.len());
+        assert!(found(0) == 0 || found(1) > 0, "min_score dropped every fused document");
+        assert_eq!(found(2), 0, "min_score compared a non-cosine score");
+    } else {
+        // no sparse model configured
+        print_response(res, "POST /admin/retrieval/compare hybrid unavailable (User 3 admin)").await?;
+    }
+
+    info!("Creating an evaluation dataset as user3 ...");
+    let res = user3_client
+        .post("http://localhost:8000/admin/evals/datasets")
+        .json(&json!({
+            "name": format!("quick-dev-{}", std::process::id()),
+            "cases": [
+                { "question": "What is the knowledge base about?", "expected_doc_ids": [] }
+            ]
+        }))
+        .send()
+        .await?;
+    let dataset = print_response(res, "POST /admin/evals/datasets (User 3 admin)").await?;
+    assert_eq!(dataset["cases"].as_array().map(|c| c.len()), Some(1));
+
+    let res = user3_client
+        .get("http://localhost:8000/admin/evals/runs")
+        .query(&[("dataset_id", dataset["id"].to_string())])
+        .send()
+        .await?;
+    print_response(res, "GET /admin/evals/runs (User 3 admin)").await?;
+
+    info!("Previewing a prompt template as user3 ...");
+    let res = user3_client
+        .post("http://localhost:8000/admin/prompts/preview")
+        .json(&json!({
+            "template": "Answer briefly: {{ question }}",
+            "variables": { "question": "What is the knowledge base about?" }
+        }))
+        .send()
+        .await?;
+    let preview = print_response(res, "POST /admin/prompts/preview (User 3 admin)").await?;
+    assert_eq!(preview["rendered"], "Answer briefly: What is the knowledge base about?");
+
+    info!("Creating, renaming and searching a conversation as user3 ...");
+    let res = user3_client
+        .post("http://localhost:8000/api/chat/conversations")
+        .json(&json!({ "title": "Quick dev chat" }))
+        .send()
+        .await?;
+    let conv = print_response(res, "POST /api/chat/conversations (User 3)").await?;
+    let conv_url = format!("http://localhost:8000/api/chat/conversations/{}", conv["id"]);
+
+    let res = user3_client
+        .put(&conv_url)
+        .json(&json!({ "title": "Quick dev onboarding chat", "pinned": true }))
+        .send()
+        .await?;
+    let renamed = print_response(res, "PUT /api/chat/conversations/:id (User 3)").await?;
+    assert_eq!(renamed["pinned"], true);
+
+    let res = user3_client
+        .get("http://localhost:8000/api/chat/search")
+        .query(&[("q", "onboarding")])
+        .send()
+        .await?;
+    let hits = print_response(res, "GET /api/chat/search (User 3)").await?;
+    assert!(hits.as_array().is_some_and(|h| h.iter().any(|hit| hit["conversation_id"] == conv["id"])));
+
+    info!("Checking that user1 cannot reach user3's conversation ...");
+    let user1_client = Client::builder()
+        .cookie_store(true)
+        .build()?;
+    let res = user1_client
+        .post("http://localhost:8000/api/login")
+        .json(&json!({ "username": "User 1 - Updated", "password": "updated123" }))
+        .send()
+        .await?;
+    print_response(res, "POST /api/login (User 1, new pwd)").await?;
+    let not_found = reqwest::StatusCode::NOT_FOUND;
+    let res = user1_client.get(format!("{conv_url}/messages")).send().await?;
+    assert_eq!(res.status(), not_found, "GET messages of another user's conversation");
+    let res = user1_client
+        .post(format!("{conv_url}/messages"))
+        .json(&json!({ "prompt": "What is the knowledge base about?" }))
+        .send()
+        .await?;
+    assert_eq!(res.status(), not_found, "POST messages to another user's conversation");
+    let res = user1_client
+        .put(&conv_url)
+        .json(&json!({ "title": "Taken over" }))
+        .send()
+        .await?;
+    assert_eq!(res.status(), not_found, "PUT another user's conversation");
+    let res = user1_client.delete(&conv_url).send().await?;
+    assert_eq!(res.status(), not_found, "DELETE another user's conversation");
+    let res = user1_client
+        .get(format!("{conv_url}/export"))
+        .query(&[("format", "json")])
+        .send()
+        .await?;
+    assert_eq!(res.status(), not_found, "export another user's conversation");
+    // a real upgrade request, so the access check runs before the handshake
+    let res = user1_client
+        .get(format!("{conv_url}/ws"))
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .send()
+        .await?;
+    assert_eq!(res.status(), not_found, "open the socket of another user's conversation");
+
+    info!("Sharing the conversation with user1 as viewer ...");
+    let res = user3_client
+        .put(format!("{conv_url}/collaborators/User 1 - Updated"))
+        .json(&json!({ "role": "viewer" }))
+        .send()
+        .await?;
+    print_response(res, "PUT /api/chat/conversations/:id/collaborators/:username viewer (User 3)").await?;
+    let res = user1_client.get(format!("{conv_url}/messages")).send().await?;
+    assert!(res.status().is_success(), "a viewer reads the messages");
+    let res = user1_client
+        .post(format!("{conv_url}/messages"))
+        .json(&json!({ "prompt": "What is the knowledge base about?" }))
+        .send()
+        .await?;
+    assert_eq!(res.status(), not_found, "a viewer cannot post");
+    let res = user1_client.delete(&conv_url).send().await?;
+    assert_eq!(res.status(), not_found, "a viewer cannot delete");
+
+    let res = user3_client
+        .post(&format!("{conv_url}/messages"))
+        .json(&json!({ "prompt": "What is 17 * 23?", "agent": true }))
+        .send()
+        .await?;
+    assert_eq!(res.status(), reqwest::StatusCode::ACCEPTED);
+    let sent = print_response(res, "POST /api/chat/conversations/:id/messages agent (User 3)").await?;
+    assert!(sent["generation_id"].is_string());
+
+    let res = user3_client
+        .post(&format!("http://localhost:8000/api/tasks/extract/conversations/{}", conv["id"]))
+        .json(&json!({}))
+        .send()
+        .await?;
+    let items = print_response(res, "POST /api/tasks/extract/conversations/:id (User 3)").await?;
+    if let Some(proposal) = items["proposals"].as_array().and_then(|p| p.first()) {
+        let res = user3_client
+            .post("http://localhost:8000/api/tasks/accept")
+            .json(&json!({ "tasks": [proposal] }))
+            .send()
+            .await?;
+        assert_eq!(res.status(), reqwest::StatusCode::CREATED);
+        let accepted = print_response(res, "POST /api/tasks/accept (User 3)").await?;
+        assert!(accepted[0]["source_message_id"].is_i64());
+    }
+
+    let res = user3_client
+        .get(&format!("{conv_url}/export"))
+        .query(&[("format", "json")])
+        .send()
+        .await?;
+    let export = print_response(res, "GET /api/chat/conversations/:id/export (User 3)").await?;
+    assert_eq!(export["title"], "Quick dev onboarding chat");
+
+    let res = user3_client
+        .post("http://localhost:8000/api/chat/import")
+        .json(&export)
+        .send()
+        .await?;
+    assert_eq!(res.status(), reqwest::StatusCode::CREATED);
+    let imported = print_response(res, "POST /api/chat/import (User 3)").await?;
+    let res = user3_client
+        .delete(&format!("http://localhost:8000/api/chat/conversations/{}", imported["id"]))
+        .send()
+        .await?;
+    assert_eq!(res.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let res = user3_client.delete(&conv_url).send().await?;
+    assert_eq!(res.status(), reqwest::StatusCode::NO_CONTENT);
+
+    info!("Reading the answer feedback summary as user3 ...");
+    let res = user3_client
+        .get("http://localhost:8000/admin/feedback/summary")
+        .send()
+        .await?;
+    let summary = print_response(res, "GET /admin/feedback/summary (User 3 admin)").await?;
+    assert!(summary.is_array());
+
     //----------------------------------
     // 10) Final Cleanup and Confirmation
     //----------------------------------